@@ -3,7 +3,9 @@
 
 layout (location = 0) in vec3 aPos;
 layout (location = 1) in vec3 aNormal;
+layout (location = 8) in mat4 aInstance; // EXT_mesh_gpu_instancing, relative to model
 
+uniform bool instanced;
 uniform mat4 model;
 uniform mat4 view;
 uniform mat4 projection;
@@ -11,11 +13,16 @@ uniform mat4 arc;
 
 out vec3 Pos;
 out vec3 Normal;
+out vec3 WorldPos;
+out vec3 WorldNormal;
 
 void main() {
-    mat4 nmodel = arc * model;
+    mat4 world = instanced ? model * aInstance : model;
+    mat4 nmodel = arc * world;
     Pos = vec3(view * nmodel * vec4(aPos, 1.0));
     Normal = mat3(inverse(transpose(nmodel))) * aNormal;
+    WorldPos = vec3(world * vec4(aPos, 1.0));
+    WorldNormal = mat3(inverse(transpose(world))) * aNormal;
     gl_Position = projection * vec4(Pos, 1.0);
 }
 #end vertex
@@ -25,9 +32,31 @@ void main() {
 
 in vec3 Pos;
 in vec3 Normal;
+in vec3 WorldPos;
+in vec3 WorldNormal;
 
 out vec4 Color;
 
+// punctual lights in world space, uploaded by the renderer
+#define MAX_LIGHTS 8
+#define DIRECTIONAL 0
+#define POINT 1
+#define SPOT 2
+
+struct Light {
+    int kind;
+    vec3 position;
+    vec3 direction;
+    vec3 color;
+    float intensity;
+    float range; // 0 means infinite
+    float inner_cone;
+    float outer_cone;
+};
+
+uniform Light lights[MAX_LIGHTS];
+uniform int light_count;
+
 // TODO use material and light structs as arguments
 vec3 ads_light(vec3 normal, vec3 position) {
     const vec3 light_pos = vec3(-0.3, 0.3, 0.3);
@@ -58,7 +87,46 @@ vec3 ads_light(vec3 normal, vec3 position) {
     return clamp(ambient + diffuse + specular, 0.0, 1.0);
 }
 
+// diffuse term of the scene lights, following the glTF falloff recommendations
+vec3 punctual_light(Light light, vec3 normal, vec3 position) {
+    vec3 lightv = -light.direction;
+    float attenuation = 1.0;
+
+    if (light.kind != DIRECTIONAL) {
+        vec3 to_light = light.position - position;
+        float dist = length(to_light);
+        lightv = to_light / dist;
+        attenuation = 1.0 / max(dist * dist, 0.0001);
+
+        if (light.range > 0.0) {
+            attenuation *= clamp(1.0 - pow(dist / light.range, 4.0), 0.0, 1.0);
+        }
+    }
+
+    if (light.kind == SPOT) {
+        float cos_outer = cos(light.outer_cone);
+        float cos_inner = cos(light.inner_cone);
+        float cd = dot(light.direction, -lightv);
+        attenuation *= smoothstep(cos_outer, cos_inner, cd);
+    }
+
+    const vec3 material_diffuse = vec3(1.0, 0.6, 0.0);
+    float diffuse = max(0.0, dot(lightv, normalize(normal)));
+
+    return diffuse * attenuation * light.intensity * light.color * material_diffuse;
+}
+
 void main() {
-    Color = vec4(ads_light(Normal, Pos), 1.0);
+    if (light_count == 0) {
+        Color = vec4(ads_light(Normal, Pos), 1.0);
+    } else {
+        vec3 color = vec3(1.0, 0.5, 0.0) * 0.2;
+
+        for (int i = 0; i < light_count && i < MAX_LIGHTS; i++) {
+            color += punctual_light(lights[i], WorldNormal, WorldPos);
+        }
+
+        Color = vec4(clamp(color, 0.0, 1.0), 1.0);
+    }
 }
 #end fragment
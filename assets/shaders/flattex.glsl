@@ -5,8 +5,13 @@ layout (location = 0) in vec3 aPos;
 layout (location = 2) in vec2 aTex;
 layout (location = 3) in vec4 aJoints;
 layout (location = 4) in vec4 aWeights;
+layout (location = 6) in vec4 aColor;
+layout (location = 7) in vec2 aTex1;
+layout (location = 8) in mat4 aInstance; // EXT_mesh_gpu_instancing, relative to model
 
 out vec2 TexCoords;
+out vec2 TexCoords1;
+out vec4 VertColor;
 
 // rem
 uniform mat4 joints[512];
@@ -14,16 +19,27 @@ uniform mat4 joints[512];
 uniform mat4 model;
 uniform mat4 view;
 uniform mat4 projection;
+uniform bool instanced;
+
+// see scene::Attributes, attributes missing from the vertex buffer read as (0, 0, 0, 1)
+#define HAS_SKIN 8
+uniform int attributes;
 
 void main() {
     TexCoords = aTex;
+    TexCoords1 = aTex1;
+    VertColor = aColor;
 
-   mat4 skinning = aWeights.x * joints[int(aJoints.x)] +
-    aWeights.y * joints[int(aJoints.y)] +
-    aWeights.z * joints[int(aJoints.z)] +
-    aWeights.w * joints[int(aJoints.w)];
+   mat4 skinning = mat4(1.0);
+   if ((attributes & HAS_SKIN) != 0) {
+    skinning = aWeights.x * joints[int(aJoints.x)] +
+     aWeights.y * joints[int(aJoints.y)] +
+     aWeights.z * joints[int(aJoints.z)] +
+     aWeights.w * joints[int(aJoints.w)];
+   }
 
-    mat4 mv = view * model;
+    mat4 world = instanced ? model * aInstance : model;
+    mat4 mv = view * world;
     vec4 pos = mv * skinning * vec4(aPos, 1.0);
     gl_Position = projection * pos;
 }
@@ -33,14 +49,30 @@ void main() {
 #version 330 core
 
 in vec2 TexCoords;
+in vec2 TexCoords1;
+in vec4 VertColor;
 out vec4 Col;
 
+// bits of the attributes uniform, see scene::Attributes
+#define HAS_NORMAL 1
+#define HAS_TEX_COORD_0 2
+#define HAS_TEX_COORD_1 4
+#define HAS_SKIN 8
+#define HAS_TANGENT 16
+#define HAS_COLOR 32
+
+uniform int attributes;
+
 struct Material {
     vec4 base_color;
     sampler2D base_tex;
 
     bool has_base_color;
     bool has_base_tex;
+    int base_tex_coord; // UV set of base_tex
+    int alpha_mode; // ALPHA_* below
+    float alpha_cutoff;
+    mat3 base_uv_transform; // KHR_texture_transform
 
     float metallic;
     float roughness;
@@ -53,16 +85,39 @@ struct Material {
 
 uniform Material material;
 
+// see AlphaMode::id
+#define ALPHA_OPAQUE 0
+#define ALPHA_MASK 1
+
+vec2 uv_set(int index) {
+    return (index == 1 && (attributes & HAS_TEX_COORD_1) != 0) ? TexCoords1 : TexCoords;
+}
+
 void main() {
+    vec2 base_uv = (material.base_uv_transform * vec3(uv_set(material.base_tex_coord), 1.0)).xy;
+
     if (material.has_base_tex && material.has_base_color) {
-        Col = texture(material.base_tex, TexCoords) * material.base_color;
+        Col = texture(material.base_tex, base_uv) * material.base_color;
     } else if (material.has_base_tex) {
-        Col = texture(material.base_tex, TexCoords);
+        Col = texture(material.base_tex, base_uv);
     }
     else if (material.has_base_color) {
         Col = material.base_color;
     } else {
         Col = vec4(1.0, 1.0, 1.0, 1.0);
     }
+
+    if ((attributes & HAS_COLOR) != 0) {
+        Col *= VertColor;
+    }
+
+    if (material.alpha_mode == ALPHA_MASK) {
+        if (Col.a < material.alpha_cutoff) {
+            discard;
+        }
+        Col.a = 1.0;
+    } else if (material.alpha_mode == ALPHA_OPAQUE) {
+        Col.a = 1.0;
+    }
 }
 #end fragment
@@ -0,0 +1,162 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use glboot::scene::{Animations, Mode, SceneData};
+
+use std::{fs, path::PathBuf};
+
+const JOINTS: usize = 60;
+const KEYS: usize = 10_000; // close to three minutes of motion capture at 60 fps
+
+// a chain of joints with a rotation and a translation key on every frame
+fn mocap_clip() -> PathBuf {
+    let dir = std::env::temp_dir().join("glboot_bench");
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut bin: Vec<u8> = Vec::new();
+    for k in 0..KEYS {
+        bin.extend_from_slice(&(k as f32 / 60.0).to_le_bytes());
+    }
+    for j in 0..JOINTS {
+        for k in 0..KEYS {
+            let angle = (k + j) as f32 * 0.01;
+            for v in [0.0, (angle / 2.0).sin(), 0.0, (angle / 2.0).cos()].iter() {
+                bin.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        for k in 0..KEYS {
+            for v in [0.0, 1.0, (k as f32 * 0.05).sin()].iter() {
+                bin.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+    }
+
+    let times = KEYS * 4;
+    let channel_bytes = KEYS * 28;
+    let mut views = vec![format!(
+        r#"{{"buffer": 0, "byteOffset": 0, "byteLength": {}}}"#,
+        times
+    )];
+    let mut accessors = vec![format!(
+        r#"{{"bufferView": 0, "componentType": 5126, "count": {}, "type": "SCALAR", "min": [0.0], "max": [{}]}}"#,
+        KEYS,
+        (KEYS - 1) as f32 / 60.0
+    )];
+    let mut nodes = Vec::new();
+    let mut channels = Vec::new();
+    let mut samplers = Vec::new();
+
+    for j in 0..JOINTS {
+        let offset = times + j * channel_bytes;
+        views.push(format!(
+            r#"{{"buffer": 0, "byteOffset": {}, "byteLength": {}}}"#,
+            offset,
+            KEYS * 16
+        ));
+        views.push(format!(
+            r#"{{"buffer": 0, "byteOffset": {}, "byteLength": {}}}"#,
+            offset + KEYS * 16,
+            KEYS * 12
+        ));
+        accessors.push(format!(
+            r#"{{"bufferView": {}, "componentType": 5126, "count": {}, "type": "VEC4"}}"#,
+            j * 2 + 1,
+            KEYS
+        ));
+        accessors.push(format!(
+            r#"{{"bufferView": {}, "componentType": 5126, "count": {}, "type": "VEC3"}}"#,
+            j * 2 + 2,
+            KEYS
+        ));
+
+        let children = if j + 1 < JOINTS {
+            format!(r#", "children": [{}]"#, j + 1)
+        } else {
+            String::new()
+        };
+        nodes.push(format!(r#"{{"name": "Joint{}"{}}}"#, j, children));
+
+        for (i, path) in ["rotation", "translation"].iter().enumerate() {
+            let sampler = j * 2 + i;
+            samplers.push(format!(r#"{{"input": 0, "output": {}}}"#, sampler + 1));
+            channels.push(format!(
+                r#"{{"sampler": {}, "target": {{"node": {}, "path": "{}"}}}}"#,
+                sampler, j, path
+            ));
+        }
+    }
+
+    let gltf = format!(
+        r#"{{
+  "asset": {{"version": "2.0"}},
+  "scene": 0,
+  "scenes": [{{"nodes": [0]}}],
+  "nodes": [{}],
+  "animations": [{{"name": "Mocap", "channels": [{}], "samplers": [{}]}}],
+  "accessors": [{}],
+  "bufferViews": [{}],
+  "buffers": [{{"uri": "mocap.bin", "byteLength": {}}}]
+}}"#,
+        nodes.join(", "),
+        channels.join(", "),
+        samplers.join(", "),
+        accessors.join(", "),
+        views.join(", "),
+        bin.len()
+    );
+
+    fs::write(dir.join("mocap.bin"), &bin).unwrap();
+    fs::write(dir.join("mocap.gltf"), gltf).unwrap();
+
+    dir.join("mocap.gltf")
+}
+
+// the keyframe before `time` found the way sampling did before the cursor
+fn linear_lookup(inputs: &[f32], time: f32) -> usize {
+    inputs
+        .windows(2)
+        .position(|win| time >= win[0] && time < win[1])
+        .unwrap_or(0)
+}
+
+pub fn keyframe_lookup(c: &mut Criterion) {
+    let mut data = SceneData::load(mocap_clip()).unwrap();
+    let clip = data.animations[0].clone();
+    let duration = clip.duration();
+
+    let mut animations = Animations::new(data.animations.clone());
+    animations.set_mode(Mode::Single(0));
+    animations.playback.playing = true;
+
+    let mut group = c.benchmark_group("Animation");
+
+    // what Scene::update does every frame
+    group.bench_function("mocap playback", |b| {
+        b.iter(|| animations.animate(1.0 / 60.0, &mut data.nodes))
+    });
+
+    // the same lookups as a playback frame, scanning every channel from its first keyframe
+    let inputs: Vec<f32> = (0..KEYS).map(|k| k as f32 / 60.0).collect();
+    let mut time = 0.0;
+    group.bench_function("mocap linear scan", |b| {
+        b.iter(|| {
+            time = (time + 1.0 / 60.0) % duration;
+            for _ in 0..JOINTS * 2 {
+                black_box(linear_lookup(&inputs, black_box(time)));
+            }
+        })
+    });
+
+    // scrubbing, every sample is far from the last one
+    let mut time = 0.0;
+    group.bench_function("mocap random seeks", |b| {
+        b.iter(|| {
+            time = (time + duration * 0.381_966) % duration;
+            black_box(clip.sample(time))
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(animation, keyframe_lookup);
+criterion_main!(animation);
@@ -18,6 +18,13 @@ impl Aabb {
         Self { min, max }
     }
 
+    /// Smallest box containing all `points`.
+    pub fn from_points<I: IntoIterator<Item = Vector3<f32>>>(points: I) -> Self {
+        points
+            .into_iter()
+            .fold(Self::default(), |aabb, p| aabb.surrounds(&Self::new(p, p)))
+    }
+
     pub fn surrounds(&self, other: &Self) -> Self {
         let min = Vector3::new(
             self.min.x.min(other.min.x),
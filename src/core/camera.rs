@@ -1,5 +1,5 @@
 use crate::ImRender;
-use cgmath::{InnerSpace, Matrix4, Point3, Vector3};
+use cgmath::{Deg, InnerSpace, Matrix4, Point3, Rad, Transform, Vector3};
 
 const WORLD_UP: Vector3<f32> = Vector3 {
     x: 0.0,
@@ -7,6 +7,35 @@ const WORLD_UP: Vector3<f32> = Vector3 {
     z: 0.0,
 };
 
+/// How a camera maps view space into clip space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// Uses the camera `fov` as the vertical field of view, a missing aspect ratio means the viewport's
+    /// and a missing `zfar` an infinite projection.
+    Perspective {
+        aspect_ratio: Option<f32>,
+        znear: f32,
+        zfar: Option<f32>,
+    },
+    /// `xmag` and `ymag` are half the width and height of the view volume.
+    Orthographic {
+        xmag: f32,
+        ymag: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::Perspective {
+            aspect_ratio: None,
+            znear: 0.1,
+            zfar: Some(100.0),
+        }
+    }
+}
+
 /// A primitive camera
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Camera {
@@ -15,6 +44,7 @@ pub struct Camera {
     pub up: Vector3<f32>,
     pub right: Vector3<f32>,
     pub fov: f32,
+    pub projection: Projection,
 }
 
 impl Camera {
@@ -35,6 +65,20 @@ impl Camera {
             up,
             right,
             fov: 45.0,
+            projection: Projection::default(),
+        }
+    }
+
+    /// Returns this camera moved by `transform`, used to place cameras defined in a node local space.
+    pub fn transformed(&self, transform: Matrix4<f32>) -> Self {
+        let pos = transform.transform_point(self.pos);
+        let front = transform.transform_vector(self.front);
+        let up = transform.transform_vector(self.up);
+
+        Camera {
+            projection: self.projection,
+            fov: self.fov,
+            ..Self::with_up(pos, front, up)
         }
     }
 
@@ -44,10 +88,67 @@ impl Camera {
         Matrix4::look_at(self.pos, self.pos + self.front, self.up)
     }
 
-    // Just a small helper
-    #[inline]
+    /// Returns the projection matrix of this camera for a `w` by `h` viewport
     pub fn get_projection(&self, w: f32, h: f32) -> Matrix4<f32> {
-        cgmath::perspective(cgmath::Deg(self.fov), w as f32 / h as f32, 0.1_f32, 100f32)
+        match self.projection {
+            Projection::Perspective {
+                aspect_ratio,
+                znear,
+                zfar: Some(zfar),
+            } => cgmath::perspective(Deg(self.fov), aspect_ratio.unwrap_or(w / h), znear, zfar),
+            Projection::Perspective {
+                aspect_ratio,
+                znear,
+                zfar: None,
+            } => {
+                // infinite projection, as described in the glTF spec
+                let f = 1.0 / (Rad::from(Deg(self.fov)).0 / 2.0).tan();
+                let aspect = aspect_ratio.unwrap_or(w / h);
+
+                #[rustfmt::skip]
+                let mat = Matrix4::new(
+                    f / aspect, 0.0, 0.0, 0.0,
+                    0.0, f, 0.0, 0.0,
+                    0.0, 0.0, -1.0, -1.0,
+                    0.0, 0.0, -2.0 * znear, 0.0,
+                );
+                mat
+            }
+            Projection::Orthographic {
+                xmag,
+                ymag,
+                znear,
+                zfar,
+            } => cgmath::ortho(-xmag, xmag, -ymag, ymag, znear, zfar),
+        }
+    }
+}
+
+impl<'a> From<gltf::Camera<'a>> for Camera {
+    /// glTF cameras look down -Z with +Y up in their node local space.
+    fn from(camera: gltf::Camera<'a>) -> Self {
+        let mut this = Camera::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+
+        match camera.projection() {
+            gltf::camera::Projection::Perspective(p) => {
+                this.fov = Deg::from(Rad(p.yfov())).0;
+                this.projection = Projection::Perspective {
+                    aspect_ratio: p.aspect_ratio(),
+                    znear: p.znear(),
+                    zfar: p.zfar(),
+                };
+            }
+            gltf::camera::Projection::Orthographic(o) => {
+                this.projection = Projection::Orthographic {
+                    xmag: o.xmag(),
+                    ymag: o.ymag(),
+                    znear: o.znear(),
+                    zfar: o.zfar(),
+                };
+            }
+        }
+
+        this
     }
 }
 
@@ -119,31 +119,30 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         // scene
         //     .borrow_mut()
         //     .update(window.glfw.get_time() as f32 - time);
-        aabb_program.set_uniform("view", camera.borrow().get_matrix());
-        aabb_program.set_uniform(
-            "proj",
-            camera
-                .borrow()
-                .get_projection(window.width as f32, window.height as f32),
+
+        // look through the imported camera if one is selected
+        let view_camera = scene.borrow().view_camera().unwrap_or(*camera.borrow());
+        let (view, projection) = (
+            view_camera.get_matrix(),
+            view_camera.get_projection(window.width as f32, window.height as f32),
         );
+
+        aabb_program.set_uniform("view", view);
+        aabb_program.set_uniform("proj", projection);
+        {
+            let mut renderer = renderer.borrow_mut();
+            renderer.main.set_uniform("view", view);
+            renderer.main.set_uniform("projection", projection);
+        }
         // let this_time = window.glfw.get_time() as f32;
         // last_time = this_time;
-        renderer
-            .borrow_mut()
-            .render(&scene.borrow() /*, &mut aabb_program*/);
+        renderer.borrow_mut().render(
+            &scene.borrow(),
+            view_camera.pos, /*, &mut aabb_program*/
+        );
         // frames += 1;
         imgui.draw(&mut window);
 
-        {
-            // let mut program = program.borrow_mut();
-            renderer.borrow_mut().main.set_uniform(
-                "projection",
-                camera
-                    .borrow()
-                    .get_projection(window.width as f32, window.height as f32),
-            );
-        }
-
         window.update();
 
         // if window.glfw.get_time() as f32 - timer > 1.0 {
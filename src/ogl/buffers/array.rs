@@ -1,13 +1,17 @@
 use gl::types::*;
-use std::{convert::TryInto, ffi::c_void, mem};
+use std::{ffi::c_void, mem};
 
 use super::vertex::VertexBuffer;
 
 #[derive(Debug, Default)]
 pub struct BufferElement {
+    pub location: u32,
     pub count: i32,
     pub ty: GLenum,
     pub size: u32,
+    pub normalized: bool, // integers are mapped to [0, 1] or [-1, 1]
+    pub divisor: u32,     // 0 advances per vertex, n every n instances
+    offset: u32,
 }
 
 #[derive(Debug, Default)]
@@ -17,17 +21,46 @@ pub struct Layout {
 }
 
 impl Layout {
+    /// Adds an element at the location following the last one.
     pub fn push<T>(&mut self, count: i32, ty: GLenum) {
+        let location = self.elements.last().map_or(0, |elem| elem.location + 1);
+
+        self.push_at::<T>(location, count, ty, false);
+    }
+
+    /// Adds an element at a fixed shader location, so layouts can skip the attributes they don't have.
+    pub fn push_at<T>(&mut self, location: u32, count: i32, ty: GLenum, normalized: bool) {
         let size = mem::size_of::<T>();
 
         self.elements.push(BufferElement {
+            location,
             count,
             ty,
             size: size as u32,
+            normalized,
+            divisor: 0,
+            offset: self.stride as u32,
         });
 
         self.stride += size as i32 * count;
     }
+
+    /// Adds a per instance element, advancing once every `divisor` instances.
+    pub fn push_instanced<T>(&mut self, location: u32, count: i32, ty: GLenum, divisor: u32) {
+        self.push_at::<T>(location, count, ty, false);
+        self.elements.last_mut().unwrap().divisor = divisor;
+    }
+
+    #[inline]
+    pub fn elements(&self) -> &[BufferElement] {
+        &self.elements
+    }
+
+    /// Size in bytes of a vertex.
+    #[inline]
+    pub fn stride(&self) -> i32 {
+        self.stride
+    }
 }
 
 #[derive(Debug, Default)]
@@ -56,20 +89,19 @@ impl VertexArray {
         self.bind();
         buffer.bind();
 
-        let mut offset = 0;
-        for (i, elem) in layout.elements.iter().enumerate() {
+        for elem in layout.elements.iter() {
             unsafe {
-                gl::EnableVertexAttribArray(i.try_into().unwrap());
+                gl::EnableVertexAttribArray(elem.location);
                 gl::VertexAttribPointer(
-                    i.try_into().unwrap(),
+                    elem.location,
                     elem.count,
                     elem.ty,
-                    gl::FALSE,
+                    if elem.normalized { gl::TRUE } else { gl::FALSE },
                     layout.stride,
-                    (offset * elem.size) as *const c_void,
+                    elem.offset as *const c_void,
                 );
+                gl::VertexAttribDivisor(elem.location, elem.divisor);
             }
-            offset += elem.count as u32;
         }
 
         self.unbind();
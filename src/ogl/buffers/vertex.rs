@@ -23,6 +23,23 @@ impl VertexBuffer {
         }
     }
 
+    /// Creates a new vertex buffer initialized with the slice data that can be updated with `write`.
+    pub fn dynamic<T>(data: &[T]) -> Self {
+        unsafe {
+            let mut vbo = 0;
+            gl::CreateBuffers(1, &mut vbo);
+
+            gl::NamedBufferStorage(
+                vbo,
+                (data.len() * mem::size_of::<T>()) as GLsizeiptr,
+                data.as_ptr() as *const c_void,
+                gl::DYNAMIC_STORAGE_BIT,
+            );
+
+            VertexBuffer(vbo)
+        }
+    }
+
     /// Creates a new empty buffer with `size` * `size_of::<T>()` bytes
     pub fn empty<T>(size: usize) -> Self {
         unsafe {
@@ -1,4 +1,5 @@
-use cgmath::{Vector3, Vector4};
+use cgmath::{Matrix3, Vector2, Vector3, Vector4};
+use gltf::json::Value;
 
 #[derive(Debug, Copy, Clone)]
 pub struct Material {
@@ -16,33 +17,199 @@ pub struct Material {
     pub occlusion_tex: Option<usize>,
 
     pub normal: Option<usize>,
+    pub normal_scale: f32,
 
     pub double_sided: bool,
+
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32, // only used by AlphaMode::Mask
+
+    // UV set used by each texture, 0 or 1
+    pub base_tex_coord: u32,
+    pub emissive_tex_coord: u32,
+    pub metallic_tex_coord: u32,
+    pub occlusion_tex_coord: u32,
+    pub normal_tex_coord: u32,
+
+    // UV transform of each texture, gltf doesn't read the normal and occlusion ones yet
+    pub base_tex_transform: TextureTransform,
+    pub emissive_tex_transform: TextureTransform,
+    pub metallic_tex_transform: TextureTransform,
+    pub occlusion_tex_transform: TextureTransform,
+    pub normal_tex_transform: TextureTransform,
+
+    // extensions, the blocks are None when the material doesn't use them
+    pub emissive_strength: f32,
+    pub clearcoat: Option<Clearcoat>,
+    pub sheen: Option<Sheen>,
+    pub transmission: Option<Transmission>,
+    pub ior: f32,
+    pub unlit: bool,
+}
+
+/// KHR_materials_clearcoat, a clear coating layered over the material.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Clearcoat {
+    pub factor: f32,
+    pub tex: Option<usize>,
+    pub roughness: f32,
+    pub roughness_tex: Option<usize>,
+    pub normal_tex: Option<usize>,
+
+    pub tex_coord: u32,
+    pub roughness_tex_coord: u32,
+    pub normal_tex_coord: u32,
+}
+
+/// KHR_materials_sheen, the back scattering of cloth like materials.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sheen {
+    pub color: Vector3<f32>,
+    pub color_tex: Option<usize>,
+    pub roughness: f32,
+    pub roughness_tex: Option<usize>,
+
+    pub color_tex_coord: u32,
+    pub roughness_tex_coord: u32,
+}
+
+/// KHR_materials_transmission, light going through thin surfaces like glass.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transmission {
+    pub factor: f32,
+    pub tex: Option<usize>,
+    pub tex_coord: u32,
+}
+
+/// How the alpha of the base color is used, see `Renderer::render` for the drawing order.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AlphaMode {
+    Opaque,
+    Mask, // alpha tested against `alpha_cutoff`
+    Blend,
+}
+
+impl AlphaMode {
+    /// Value of the `material.alpha_mode` uniform.
+    pub fn id(self) -> i32 {
+        match self {
+            AlphaMode::Opaque => 0,
+            AlphaMode::Mask => 1,
+            AlphaMode::Blend => 2,
+        }
+    }
+}
+
+impl From<gltf::material::AlphaMode> for AlphaMode {
+    fn from(mode: gltf::material::AlphaMode) -> Self {
+        match mode {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        }
+    }
+}
+
+/// Offset, rotation and scale applied to the UVs of a texture, from KHR_texture_transform.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextureTransform {
+    pub offset: Vector2<f32>,
+    pub rotation: f32, // radians, counter-clockwise
+    pub scale: Vector2<f32>,
+}
+
+impl TextureTransform {
+    /// The matrix applied to the UVs: translation * rotation * scale.
+    pub fn matrix(&self) -> Matrix3<f32> {
+        let (sin, cos) = self.rotation.sin_cos();
+        let (offset, scale) = (self.offset, self.scale);
+
+        #[rustfmt::skip]
+        let matrix = Matrix3::new(
+            cos * scale.x, -sin * scale.x, 0.0,
+            sin * scale.y, cos * scale.y,  0.0,
+            offset.x,      offset.y,       1.0,
+        );
+
+        matrix
+    }
+
+    #[inline]
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Default for TextureTransform {
+    fn default() -> Self {
+        Self {
+            offset: Vector2::new(0.0, 0.0),
+            rotation: 0.0,
+            scale: Vector2::new(1.0, 1.0),
+        }
+    }
+}
+
+impl<'a> From<gltf::texture::TextureTransform<'a>> for TextureTransform {
+    fn from(transform: gltf::texture::TextureTransform) -> Self {
+        Self {
+            offset: transform.offset().into(),
+            rotation: transform.rotation(),
+            scale: transform.scale().into(),
+        }
+    }
 }
 
 impl<'a> From<gltf::Material<'a>> for Material {
     fn from(mat: gltf::Material) -> Self {
         let metallic_roughness = mat.pbr_metallic_roughness();
         let base_color = metallic_roughness.base_color_factor().into();
-        let base_tex = metallic_roughness
-            .base_color_texture()
-            .map(|info| info.texture().index());
+        let base_info = metallic_roughness.base_color_texture();
+        let base_tex = base_info.as_ref().map(|info| info.texture().index());
 
         let metallic = metallic_roughness.metallic_factor();
         let roughness = metallic_roughness.roughness_factor();
-        let metallic_tex = metallic_roughness
-            .metallic_roughness_texture()
-            .map(|info| info.texture().index());
+        let metallic_info = metallic_roughness.metallic_roughness_texture();
+        let metallic_tex = metallic_info.as_ref().map(|info| info.texture().index());
 
-        let normal = mat.normal_texture().map(|norm| norm.texture().index());
-        let (occlusion_tex, occlusion_str) = mat
-            .occlusion_texture()
+        let normal_info = mat.normal_texture();
+        let normal = normal_info.as_ref().map(|norm| norm.texture().index());
+        let occlusion_info = mat.occlusion_texture();
+        let (occlusion_tex, occlusion_str) = occlusion_info
+            .as_ref()
             .map(|occ| (Some(occ.texture().index()), occ.strength()))
             .unwrap_or((None, 0.0));
 
         let double_sided = mat.double_sided();
         let emissive_factor = mat.emissive_factor().into();
-        let emissive_tex = mat.emissive_texture().map(|em| em.texture().index());
+        let emissive_info = mat.emissive_texture();
+        let emissive_tex = emissive_info.as_ref().map(|em| em.texture().index());
+
+        let transform = |info: &Option<gltf::texture::Info>| {
+            info.as_ref()
+                .and_then(|info| info.texture_transform())
+                .map_or_else(TextureTransform::default, TextureTransform::from)
+        };
+        let base_tex_transform = transform(&base_info);
+        let emissive_tex_transform = transform(&emissive_info);
+        let metallic_tex_transform = transform(&metallic_info);
+        // the texCoord of a KHR_texture_transform replaces the one of its textureInfo
+        let tex_coord = |info: &Option<gltf::texture::Info>| {
+            info.as_ref().map_or(0, |info| {
+                info.texture_transform()
+                    .and_then(|transform| transform.tex_coord())
+                    .unwrap_or_else(|| info.tex_coord())
+            })
+        };
+
+        let transmission = mat.transmission().map(|t| {
+            let info = t.transmission_texture();
+            Transmission {
+                factor: t.transmission_factor(),
+                tex: info.as_ref().map(|info| info.texture().index()),
+                tex_coord: info.map_or(0, |info| info.tex_coord()),
+            }
+        });
 
         Self {
             base_color,
@@ -51,11 +218,130 @@ impl<'a> From<gltf::Material<'a>> for Material {
             roughness,
             metallic_tex,
             normal,
+            normal_scale: normal_info.as_ref().map_or(1.0, |info| info.scale()),
             occlusion_tex,
             occlusion_str,
             double_sided,
+            alpha_mode: mat.alpha_mode().into(),
+            alpha_cutoff: mat.alpha_cutoff().unwrap_or(0.5),
             emissive_factor,
             emissive_tex,
+            base_tex_coord: tex_coord(&base_info),
+            emissive_tex_coord: tex_coord(&emissive_info),
+            metallic_tex_coord: tex_coord(&metallic_info),
+            occlusion_tex_coord: occlusion_info.map_or(0, |info| info.tex_coord()),
+            normal_tex_coord: normal_info.map_or(0, |info| info.tex_coord()),
+            base_tex_transform,
+            emissive_tex_transform,
+            metallic_tex_transform,
+            occlusion_tex_transform: TextureTransform::default(),
+            normal_tex_transform: TextureTransform::default(),
+            emissive_strength: 1.0,
+            clearcoat: None,
+            sheen: None,
+            transmission,
+            ior: mat.ior().unwrap_or(1.5),
+            unlit: mat.unlit(),
+        }
+    }
+}
+
+impl Material {
+    /// Reads the `KHR_texture_transform` of the normal and occlusion textures from the raw JSON
+    /// of the material, gltf only gives it for the other textures.
+    pub fn read_texture_transforms(&mut self, material: &Value) {
+        let slots = [
+            (
+                "normalTexture",
+                &mut self.normal_tex_transform,
+                &mut self.normal_tex_coord,
+            ),
+            (
+                "occlusionTexture",
+                &mut self.occlusion_tex_transform,
+                &mut self.occlusion_tex_coord,
+            ),
+        ];
+
+        for (name, transform, tex_coord) in slots {
+            let ext = &material[name]["extensions"]["KHR_texture_transform"];
+            if !ext.is_object() {
+                continue;
+            }
+
+            let vector = |name, default| {
+                ext.get(name)
+                    .and_then(|v| gltf::json::deserialize::from_value::<[f32; 2]>(v.clone()).ok())
+                    .map_or(default, Vector2::from)
+            };
+            *transform = TextureTransform {
+                offset: vector("offset", Vector2::new(0.0, 0.0)),
+                rotation: ext
+                    .get("rotation")
+                    .and_then(Value::as_f64)
+                    .map_or(0.0, |r| r as f32),
+                scale: vector("scale", Vector2::new(1.0, 1.0)),
+            };
+            if let Some(index) = ext.get("texCoord").and_then(Value::as_u64) {
+                *tex_coord = index as u32;
+            }
+        }
+    }
+
+    /// Reads the extensions gltf doesn't know about from the raw `extensions` object of the
+    /// material: emissive strength, clearcoat and sheen.
+    pub fn read_extensions(&mut self, extensions: &Value) {
+        let float = |ext: &Value, name, default| {
+            ext.get(name)
+                .and_then(Value::as_f64)
+                .map_or(default, |v| v as f32)
+        };
+        // (texture index, UV set) of a textureInfo
+        let texture = |ext: &Value, name| {
+            ext.get(name).map_or((None, 0), |info| {
+                let index = info.get("index").and_then(Value::as_u64);
+                let tex_coord = info.get("texCoord").and_then(Value::as_u64).unwrap_or(0);
+                (index.map(|i| i as usize), tex_coord as u32)
+            })
+        };
+
+        if let Some(ext) = extensions.get("KHR_materials_emissive_strength") {
+            self.emissive_strength = float(ext, "emissiveStrength", 1.0);
+        }
+
+        if let Some(ext) = extensions.get("KHR_materials_clearcoat") {
+            let (tex, tex_coord) = texture(ext, "clearcoatTexture");
+            let (roughness_tex, roughness_tex_coord) = texture(ext, "clearcoatRoughnessTexture");
+            let (normal_tex, normal_tex_coord) = texture(ext, "clearcoatNormalTexture");
+
+            self.clearcoat = Some(Clearcoat {
+                factor: float(ext, "clearcoatFactor", 0.0),
+                tex,
+                roughness: float(ext, "clearcoatRoughnessFactor", 0.0),
+                roughness_tex,
+                normal_tex,
+                tex_coord,
+                roughness_tex_coord,
+                normal_tex_coord,
+            });
+        }
+
+        if let Some(ext) = extensions.get("KHR_materials_sheen") {
+            let color = ext
+                .get("sheenColorFactor")
+                .and_then(|c| gltf::json::deserialize::from_value::<[f32; 3]>(c.clone()).ok())
+                .unwrap_or([0.0; 3]);
+            let (color_tex, color_tex_coord) = texture(ext, "sheenColorTexture");
+            let (roughness_tex, roughness_tex_coord) = texture(ext, "sheenRoughnessTexture");
+
+            self.sheen = Some(Sheen {
+                color: color.into(),
+                color_tex,
+                roughness: float(ext, "sheenRoughnessFactor", 0.0),
+                roughness_tex,
+                color_tex_coord,
+                roughness_tex_coord,
+            });
         }
     }
 }
@@ -69,11 +355,106 @@ impl Default for Material {
             roughness: 0.0,
             metallic_tex: None,
             normal: None,
+            normal_scale: 1.0,
             occlusion_tex: None,
             occlusion_str: 1.0,
             double_sided: false,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
             emissive_factor: Vector3::new(0.0, 0.0, 0.0),
             emissive_tex: None,
+            base_tex_coord: 0,
+            emissive_tex_coord: 0,
+            metallic_tex_coord: 0,
+            occlusion_tex_coord: 0,
+            normal_tex_coord: 0,
+            base_tex_transform: TextureTransform::default(),
+            emissive_tex_transform: TextureTransform::default(),
+            metallic_tex_transform: TextureTransform::default(),
+            occlusion_tex_transform: TextureTransform::default(),
+            normal_tex_transform: TextureTransform::default(),
+            emissive_strength: 1.0,
+            clearcoat: None,
+            sheen: None,
+            transmission: None,
+            ior: 1.5,
+            unlit: false,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::test_util::test_asset;
+    use cgmath::{vec2, vec3, InnerSpace};
+
+    #[test]
+    fn uv_transform() {
+        let transform = TextureTransform {
+            offset: vec2(0.5, 0.0),
+            rotation: std::f32::consts::FRAC_PI_2,
+            scale: vec2(2.0, 1.0),
+        };
+
+        // scaled to (2, 0), rotated clockwise in UV space to (0, -2), then offset
+        let uv = transform.matrix() * vec3(1.0, 0.0, 1.0);
+        assert!((uv - vec3(0.5, -2.0, 1.0)).magnitude() < 1e-5);
+
+        assert!(TextureTransform::default().is_identity());
+        assert_eq!(
+            TextureTransform::default().matrix(),
+            cgmath::SquareMatrix::identity()
+        );
+    }
+
+    #[test]
+    fn alpha_modes() {
+        let path = test_asset("alpha_modes.gltf");
+        let data = crate::scene::SceneData::load(path).unwrap();
+        let modes: Vec<(AlphaMode, f32)> = data
+            .materials
+            .iter()
+            .map(|m| (m.alpha_mode, m.alpha_cutoff))
+            .collect();
+
+        assert_eq!(
+            modes,
+            vec![
+                (AlphaMode::Opaque, 0.5),
+                (AlphaMode::Mask, 0.25),
+                (AlphaMode::Blend, 0.5),
+            ]
+        );
+    }
+
+    #[test]
+    fn pbr_extensions() {
+        let path = test_asset("pbr_extensions.gltf");
+        let data = crate::scene::SceneData::load(path).unwrap();
+        let (mat, plain) = (data.materials[0], data.materials[1]);
+
+        assert_eq!(mat.emissive_strength, 4.0);
+        assert_eq!(mat.ior, 1.4);
+        assert!(mat.unlit);
+
+        let clearcoat = mat.clearcoat.unwrap();
+        assert_eq!((clearcoat.factor, clearcoat.roughness), (1.0, 0.25));
+        assert_eq!(
+            (clearcoat.normal_tex, clearcoat.normal_tex_coord),
+            (Some(0), 1)
+        );
+        assert_eq!(clearcoat.tex, None);
+
+        let sheen = mat.sheen.unwrap();
+        assert_eq!(sheen.color, vec3(0.5, 0.25, 1.0));
+        assert_eq!(sheen.roughness_tex, Some(0));
+
+        let transmission = mat.transmission.unwrap();
+        assert_eq!((transmission.factor, transmission.tex), (0.75, Some(0)));
+
+        assert_eq!(plain.emissive_strength, 1.0);
+        assert_eq!(plain.ior, 1.5);
+        assert!(plain.clearcoat.is_none() && plain.sheen.is_none() && !plain.unlit);
+    }
+}
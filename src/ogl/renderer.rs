@@ -1,8 +1,12 @@
-use cgmath::{Matrix, Matrix4, SquareMatrix};
+use cgmath::{prelude::*, Matrix4, Point3, Vector3};
 
-use super::{buffers::*, material::Material, program::ShaderProgram};
+use super::{
+    buffers::*,
+    material::{AlphaMode, Material},
+    program::ShaderProgram,
+};
 use crate::{
-    scene::{skin::Skin, Mesh, Node, Scene},
+    scene::{skin::Skin, LightKind, Node, Primitive, Scene, World},
     ImRender,
 };
 
@@ -11,6 +15,9 @@ const SCREEN_QUAD: [f32; 24] = [
     -1.0, 1.0, 0.0, 1.0, 1.0, 1.0, 1.0,
 ];
 
+/// Size of the `lights` uniform array in the shaders, extra lights are ignored.
+pub const MAX_LIGHTS: usize = 8;
+
 #[derive(Debug)]
 pub struct Renderer {
     screen: Screen,
@@ -24,6 +31,16 @@ pub struct Renderer {
     bg_col: [f32; 3],
 }
 
+// a primitive of a node waiting to be drawn
+#[derive(Debug, Copy, Clone)]
+struct DrawCall {
+    scene: usize, // index in the scenes being rendered
+    node: usize,
+    mesh: usize,
+    primitive: usize,
+    distance: f32, // squared, from the eye to the center of the primitive
+}
+
 #[derive(Debug)]
 struct Screen {
     vao: VertexArray,
@@ -60,7 +77,32 @@ impl Renderer {
         }
     }
 
-    pub fn render(&mut self, scene: &Scene /*, aabb_program: &mut ShaderProgram*/) {
+    /// Draws the scene seen from `eye`.
+    ///
+    /// Opaque primitives go first, then alpha tested ones and at last the blended ones,
+    /// from the farthest to the closest and without writing depth.
+    pub fn render(
+        &mut self,
+        scene: &Scene,
+        eye: Point3<f32>, /*, aabb_program: &mut ShaderProgram*/
+    ) {
+        self.render_scenes(&[(scene, Matrix4::identity())], eye);
+    }
+
+    /// Draws every placement of the world seen from `eye`, blended primitives are sorted
+    /// across all of them.
+    pub fn render_world(&mut self, world: &World, eye: Point3<f32>) {
+        let scenes: Vec<(&Scene, Matrix4<f32>)> = world
+            .placements
+            .iter()
+            .map(|p| (&p.scene, p.transform))
+            .collect();
+
+        self.render_scenes(&scenes, eye);
+    }
+
+    // each scene is drawn under its transform
+    fn render_scenes(&mut self, scenes: &[(&Scene, Matrix4<f32>)], eye: Point3<f32>) {
         self.front.bind();
         unsafe {
             gl::Viewport(0, 0, self.front.width, self.front.height);
@@ -71,18 +113,24 @@ impl Renderer {
 
         // scene.render(&mut self.main, aabb_program);
         self.main.bind();
-        let nodes = &scene.nodes;
-        let roots = &scene.roots;
-        let skins = &scene.skins;
-        let materials = &scene.materials;
-        let textures = &scene.textures;
-
-        for (i, tex) in textures.iter().enumerate() {
-            tex.bind(i as u32);
+        self.upload_lights(scenes);
+
+        let (opaque, masked, blended) = sort_primitives(scenes, eye);
+
+        self.draw(&opaque, scenes);
+        self.draw(&masked, scenes);
+
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::DepthMask(gl::FALSE);
         }
 
-        for root in roots.iter() {
-            self.render_node(&nodes[*root], skins, materials, nodes);
+        self.draw(&blended, scenes);
+
+        unsafe {
+            gl::DepthMask(gl::TRUE);
+            gl::Disable(gl::BLEND);
         }
 
         self.main.unbind();
@@ -111,118 +159,242 @@ impl Renderer {
         }
     }
 
-    fn render_node(&mut self, this: &Node, skins: &[Skin], materials: &[Material], nodes: &[Node]) {
-        if let Some(ref mesh) = this.mesh {
-            if let Some(skin) = this.skin {
-                let joints = &skins[skin].joints;
-                let joint_matrices: Vec<Matrix4<f32>> = joints
-                    .iter()
-                    .map(|joint| {
-                        this.global_transform.invert().unwrap()
-                            * nodes[joint.node].global_transform
-                            * joint.bind_matrix
-                    })
-                    .collect();
-
-                // dbg!(joint_matrices.len());
-
-                unsafe {
-                    let name = std::ffi::CString::new("joints").unwrap();
-                    gl::UniformMatrix4fv(
-                        gl::GetUniformLocation(self.main.0, name.as_ptr()),
-                        joint_matrices.len() as i32,
-                        gl::FALSE,
-                        joint_matrices[0].as_ptr(),
-                    );
-                }
+    /// Sends the lights of the scenes, in world space, to the main program.
+    fn upload_lights(&mut self, scenes: &[(&Scene, Matrix4<f32>)]) {
+        let main = &mut self.main;
+        let mut count = 0_i32;
+
+        let lights = scenes.iter().flat_map(|&(scene, placement)| {
+            scene
+                .node_lights()
+                .map(move |(light, transform)| (light, placement * transform))
+        });
+
+        for (i, (light, transform)) in lights.take(MAX_LIGHTS).enumerate() {
+            let position = transform.transform_point(Point3::origin()).to_vec();
+            let direction = transform.transform_vector(-Vector3::unit_z()).normalize();
+            let field = |name: &str| format!("lights[{}].{}", i, name);
+
+            main.set_uniform(&field("kind"), light.kind.id());
+            main.set_uniform(&field("position"), position);
+            main.set_uniform(&field("direction"), direction);
+            main.set_uniform(&field("color"), light.color);
+            main.set_uniform(&field("intensity"), light.intensity);
+            // zero stands for an infinite range
+            main.set_uniform(&field("range"), light.range.unwrap_or(0.0));
+
+            if let LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } = light.kind
+            {
+                main.set_uniform(&field("inner_cone"), inner_cone_angle.0);
+                main.set_uniform(&field("outer_cone"), outer_cone_angle.0);
             }
 
-            self.main.set_uniform("model", this.global_transform);
-            self.render_mesh(mesh, materials);
+            count += 1;
         }
 
-        for child in this.children.iter() {
-            self.render_node(&nodes[*child], skins, materials, nodes);
-        }
+        main.set_uniform("light_count", count);
     }
 
-    fn render_mesh(&mut self, mesh: &Mesh, materials: &[Material]) {
-        let main = &mut self.main;
+    /// Draws the primitives in order, the textures and node uniforms are only sent when the
+    /// scene or the node change.
+    fn draw(&mut self, calls: &[DrawCall], scenes: &[(&Scene, Matrix4<f32>)]) {
+        let mut current = None;
 
-        for prim in mesh.primitives.iter() {
-            if let Some(mat_index) = prim.material {
-                let material = &materials[mat_index];
+        for call in calls.iter() {
+            let (scene, placement) = scenes[call.scene];
+            let node = &scene.nodes[call.node];
 
-                if material.double_sided {
-                    unsafe {
-                        gl::Disable(gl::CULL_FACE);
-                    }
-                } else {
-                    unsafe {
-                        gl::Enable(gl::CULL_FACE);
-                    }
+            if current.is_none_or(|(s, _)| s != call.scene) {
+                for (i, tex) in scene.textures.iter().enumerate() {
+                    tex.bind(i as u32);
                 }
+            }
+            if current != Some((call.scene, call.node)) {
+                self.upload_node(node, placement, &scene.skins, &scene.nodes);
+                current = Some((call.scene, call.node));
+            }
 
-                main.set_uniform("material.base_color", material.base_color);
-                main.set_uniform("material.has_base_color", 1);
+            let prim = &scene.meshes[call.mesh].primitives[call.primitive];
 
-                if let Some(base_tex_index) = material.base_tex {
-                    main.set_uniform("material.base_tex", base_tex_index as i32);
-                    main.set_uniform("material.has_base_tex", 1);
-                } else {
-                    main.set_uniform("material.has_base_tex", 0);
-                }
+            // instanced nodes draw through their own VAOs, other nodes may use the same mesh
+            let instanced = scene.instanced_vao(call.node, call.primitive);
+            let instances = instanced.map_or(1, |_| node.instances.len() as i32);
+            self.main
+                .set_uniform("instanced", instanced.is_some() as i32);
 
-                if let Some(emissive) = material.emissive_tex {
-                    main.set_uniform("material.emissive_tex", emissive as i32);
-                    main.set_uniform("material.emissive_factor", material.emissive_factor);
-                }
+            let vao = instanced.unwrap_or(&prim.vao);
+            let material = prim
+                .variant_material(scene.active_variant())
+                .map(|m| &scene.materials[m]);
+            self.render_primitive(prim, vao, material, &node.weights, instances);
+        }
+    }
 
-                if let Some(normal_index) = material.normal {
-                    main.set_uniform("material.normal", normal_index as i32);
-                }
+    fn upload_node(
+        &mut self,
+        this: &Node,
+        placement: Matrix4<f32>,
+        skins: &[Skin],
+        nodes: &[Node],
+    ) {
+        if let Some(skin) = this.skin {
+            let joints = &skins[skin].joints;
+            let joint_matrices: Vec<Matrix4<f32>> = joints
+                .iter()
+                .map(|joint| {
+                    this.global_transform.invert().unwrap()
+                        * nodes[joint.node].global_transform
+                        * joint.bind_matrix
+                })
+                .collect();
+
+            // dbg!(joint_matrices.len());
 
-                if let Some(metal) = material.metallic_tex {
-                    main.set_uniform("material.metallic_tex", metal as i32);
-                    main.set_uniform("material.metallic", material.metallic);
-                    main.set_uniform("material.roughness", material.roughness);
-                }
+            unsafe {
+                let name = std::ffi::CString::new("joints").unwrap();
+                gl::UniformMatrix4fv(
+                    gl::GetUniformLocation(self.main.0, name.as_ptr()),
+                    joint_matrices.len() as i32,
+                    gl::FALSE,
+                    joint_matrices[0].as_ptr(),
+                );
+            }
+        }
+
+        self.main
+            .set_uniform("model", placement * this.global_transform);
+    }
+
+    fn render_primitive(
+        &mut self,
+        prim: &Primitive,
+        vao: &VertexArray,
+        material: Option<&Material>,
+        weights: &[f32],
+        instances: i32,
+    ) {
+        let main = &mut self.main;
 
-                if let Some(occlusion) = material.occlusion_tex {
-                    main.set_uniform("material.occlusion_tex", occlusion as i32);
-                    main.set_uniform("material.occlusion_str", material.occlusion_str);
+        prim.morph(weights);
+
+        if let Some(material) = material {
+            if material.double_sided {
+                unsafe {
+                    gl::Disable(gl::CULL_FACE);
                 }
             } else {
-                // shader.set_uniform("material.base_color", material.base_color);
-                main.set_uniform("material.has_base_color", 0);
+                unsafe {
+                    gl::Enable(gl::CULL_FACE);
+                }
+            }
 
-                // shader.set_uniform("material.base_tex", base_tex_index as i32);
+            main.set_uniform("material.base_color", material.base_color);
+            main.set_uniform("material.has_base_color", 1);
+            main.set_uniform("material.alpha_mode", material.alpha_mode.id());
+            main.set_uniform("material.alpha_cutoff", material.alpha_cutoff);
+
+            if let Some(base_tex_index) = material.base_tex {
+                main.set_uniform("material.base_tex", base_tex_index as i32);
+                main.set_uniform("material.base_tex_coord", material.base_tex_coord as i32);
+                main.set_uniform(
+                    "material.base_uv_transform",
+                    material.base_tex_transform.matrix(),
+                );
+                main.set_uniform("material.has_base_tex", 1);
+            } else {
                 main.set_uniform("material.has_base_tex", 0);
             }
 
-            // main.set_uniform("model", transform);
+            if let Some(emissive) = material.emissive_tex {
+                main.set_uniform("material.emissive_tex", emissive as i32);
+                main.set_uniform("material.emissive_factor", material.emissive_factor);
+                main.set_uniform(
+                    "material.emissive_tex_coord",
+                    material.emissive_tex_coord as i32,
+                );
+                main.set_uniform(
+                    "material.emissive_uv_transform",
+                    material.emissive_tex_transform.matrix(),
+                );
+            }
+
+            if let Some(normal_index) = material.normal {
+                main.set_uniform("material.normal", normal_index as i32);
+                main.set_uniform("material.normal_scale", material.normal_scale);
+                main.set_uniform(
+                    "material.normal_tex_coord",
+                    material.normal_tex_coord as i32,
+                );
+                main.set_uniform(
+                    "material.normal_uv_transform",
+                    material.normal_tex_transform.matrix(),
+                );
+            }
 
-            prim.vao.bind();
-            prim.ibo.bind();
-            main.send_uniforms();
+            if let Some(metal) = material.metallic_tex {
+                main.set_uniform("material.metallic_tex", metal as i32);
+                main.set_uniform("material.metallic", material.metallic);
+                main.set_uniform("material.roughness", material.roughness);
+                main.set_uniform(
+                    "material.metallic_tex_coord",
+                    material.metallic_tex_coord as i32,
+                );
+                main.set_uniform(
+                    "material.metallic_uv_transform",
+                    material.metallic_tex_transform.matrix(),
+                );
+            }
 
-            // TODO instancing
-            unsafe {
-                if prim.indices_count > 0 {
-                    gl::DrawElements(
-                        prim.mode,
-                        prim.indices_count,
-                        gl::UNSIGNED_INT,
-                        std::ptr::null(),
-                    );
-                } else {
-                    gl::DrawArrays(prim.mode, 0, prim.vertice_count);
-                }
-            };
+            if let Some(occlusion) = material.occlusion_tex {
+                main.set_uniform("material.occlusion_tex", occlusion as i32);
+                main.set_uniform("material.occlusion_str", material.occlusion_str);
+                main.set_uniform(
+                    "material.occlusion_tex_coord",
+                    material.occlusion_tex_coord as i32,
+                );
+                main.set_uniform(
+                    "material.occlusion_uv_transform",
+                    material.occlusion_tex_transform.matrix(),
+                );
+            }
+
+            upload_extensions(main, material);
+        } else {
+            // shader.set_uniform("material.base_color", material.base_color);
+            main.set_uniform("material.has_base_color", 0);
+            main.set_uniform("material.alpha_mode", AlphaMode::Opaque.id());
 
-            prim.ibo.unbind();
-            prim.vao.unbind();
+            // shader.set_uniform("material.base_tex", base_tex_index as i32);
+            main.set_uniform("material.has_base_tex", 0);
+            upload_extensions(main, &Material::default());
         }
+
+        // main.set_uniform("model", transform);
+        main.set_uniform("attributes", prim.attributes.bits() as i32);
+
+        vao.bind();
+        prim.ibo.bind();
+        main.send_uniforms();
+
+        unsafe {
+            if prim.indices_count > 0 {
+                gl::DrawElementsInstanced(
+                    prim.mode,
+                    prim.indices_count,
+                    prim.index_type,
+                    std::ptr::null(),
+                    instances,
+                );
+            } else {
+                gl::DrawArraysInstanced(prim.mode, 0, prim.vertice_count, instances);
+            }
+        };
+
+        prim.ibo.unbind();
+        vao.unbind();
     }
 
     #[inline]
@@ -238,6 +410,123 @@ impl Renderer {
     }
 }
 
+/// Splits the primitives of the active scene of each placement by alpha mode, the blended ones
+/// sorted back to front.
+fn sort_primitives(
+    scenes: &[(&Scene, Matrix4<f32>)],
+    eye: Point3<f32>,
+) -> (Vec<DrawCall>, Vec<DrawCall>, Vec<DrawCall>) {
+    let (mut opaque, mut masked, mut blended) = (Vec::new(), Vec::new(), Vec::new());
+
+    for (index, &(scene, placement)) in scenes.iter().enumerate() {
+        for node in scene.mesh_nodes() {
+            let this = &scene.nodes[node];
+            let mesh = this.mesh.unwrap();
+            let transform = placement * this.global_transform;
+
+            for (i, prim) in scene.meshes[mesh].primitives.iter().enumerate() {
+                let alpha_mode = prim
+                    .variant_material(scene.active_variant())
+                    .map_or(AlphaMode::Opaque, |m| scene.materials[m].alpha_mode);
+                let center = Point3::from_vec((prim.aabb.min + prim.aabb.max) * 0.5);
+                let call = DrawCall {
+                    scene: index,
+                    node,
+                    mesh,
+                    primitive: i,
+                    distance: transform.transform_point(center).distance2(eye),
+                };
+
+                match alpha_mode {
+                    AlphaMode::Opaque => opaque.push(call),
+                    AlphaMode::Mask => masked.push(call),
+                    AlphaMode::Blend => blended.push(call),
+                }
+            }
+        }
+    }
+
+    blended.sort_by(|a, b| {
+        b.distance
+            .partial_cmp(&a.distance)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    (opaque, masked, blended)
+}
+
+/// Sends the parameters of the material extensions, blocks the material doesn't use have
+/// their `has_*` flag cleared.
+fn upload_extensions(main: &mut ShaderProgram, material: &Material) {
+    main.set_uniform("material.emissive_strength", material.emissive_strength);
+    main.set_uniform("material.ior", material.ior);
+    main.set_uniform("material.unlit", material.unlit as i32);
+
+    main.set_uniform(
+        "material.has_clearcoat",
+        material.clearcoat.is_some() as i32,
+    );
+    if let Some(c) = material.clearcoat {
+        let block = "material.clearcoat";
+        main.set_uniform("material.clearcoat.factor", c.factor);
+        main.set_uniform("material.clearcoat.roughness", c.roughness);
+        upload_texture(main, block, "tex", c.tex, c.tex_coord);
+        upload_texture(
+            main,
+            block,
+            "roughness_tex",
+            c.roughness_tex,
+            c.roughness_tex_coord,
+        );
+        upload_texture(main, block, "normal_tex", c.normal_tex, c.normal_tex_coord);
+    }
+
+    main.set_uniform("material.has_sheen", material.sheen.is_some() as i32);
+    if let Some(sheen) = material.sheen {
+        let block = "material.sheen";
+        main.set_uniform("material.sheen.color", sheen.color);
+        main.set_uniform("material.sheen.roughness", sheen.roughness);
+        upload_texture(
+            main,
+            block,
+            "color_tex",
+            sheen.color_tex,
+            sheen.color_tex_coord,
+        );
+        upload_texture(
+            main,
+            block,
+            "roughness_tex",
+            sheen.roughness_tex,
+            sheen.roughness_tex_coord,
+        );
+    }
+
+    main.set_uniform(
+        "material.has_transmission",
+        material.transmission.is_some() as i32,
+    );
+    if let Some(t) = material.transmission {
+        main.set_uniform("material.transmission.factor", t.factor);
+        upload_texture(main, "material.transmission", "tex", t.tex, t.tex_coord);
+    }
+}
+
+// sets `block.name`, `block.name_coord` and `block.has_name`
+fn upload_texture(
+    main: &mut ShaderProgram,
+    block: &str,
+    name: &str,
+    tex: Option<usize>,
+    tex_coord: u32,
+) {
+    if let Some(index) = tex {
+        main.set_uniform(&format!("{}.{}", block, name), index as i32);
+        main.set_uniform(&format!("{}.{}_coord", block, name), tex_coord as i32);
+    }
+    main.set_uniform(&format!("{}.has_{}", block, name), tex.is_some() as i32);
+}
+
 impl ImRender for Renderer {
     fn render(&mut self, ui: &imgui::Ui) {
         if imgui::CollapsingHeader::new(imgui::im_str!("Renderer")).build(ui) {
@@ -1,5 +1,6 @@
 use gl::types::*;
 
+use crate::scene::Sampler;
 use image::{DynamicImage, GenericImageView};
 use std::{convert::From, ffi::c_void, path::Path};
 
@@ -147,6 +148,13 @@ impl Texture2D {
 // TODO this should be a From, just need to finsh filling the match
 impl From<gltf::image::Data> for Texture2D {
     fn from(data: gltf::image::Data) -> Self {
+        Texture2D::from_image(&data, &Sampler::default())
+    }
+}
+
+impl Texture2D {
+    /// Uploads a glTF image, filters the sampler leaves open keep the builder defaults.
+    pub fn from_image(data: &gltf::image::Data, sampler: &Sampler) -> Self {
         use gltf::image::Format;
 
         let (internal, format) = match data.format {
@@ -161,12 +169,20 @@ impl From<gltf::image::Data> for Texture2D {
             Format::R16G16B16 => (gl::RGB16UI, gl::RGB_INTEGER),
             Format::R16G16B16A16 => (gl::RGBA16UI, gl::RGBA_INTEGER),
         };
-        unsafe {
-            TextureBuilder2D::new(data.width as i32, data.height as i32)
-                .format(format)
-                .internal(internal)
-                .with_bytes(&data.pixels)
+        let mut builder = TextureBuilder2D::new(data.width as i32, data.height as i32);
+        builder
+            .format(format)
+            .internal(internal)
+            .wrap_s(sampler.wrap_s.as_gl_enum() as i32)
+            .wrap_t(sampler.wrap_t.as_gl_enum() as i32);
+        if let Some(filter) = sampler.mag_filter {
+            builder.mag_filter(filter.as_gl_enum() as i32);
         }
+        if let Some(filter) = sampler.min_filter {
+            builder.min_filter(filter.as_gl_enum() as i32);
+        }
+
+        unsafe { builder.with_bytes(&data.pixels) }
     }
 }
 
@@ -1,12 +1,31 @@
+use super::{layer::Layer, LoaderError, Playback, Pose, StateMachine};
 use crate::slerp;
 use cgmath::{prelude::*, Quaternion, Vector3, VectorSpace};
 use gltf::animation::{util::ReadOutputs, Channel as gltfChannel, Interpolation, Property};
 
+/// The clips of a scene and the playback of the selected ones.
 #[derive(Debug, Clone)]
 pub struct Animations {
     pub inner: Vec<Animation>,
-    pub mode: Mode,
-    pub paused: bool,
+    pub playback: Playback,            // shared by every selected clip
+    pub weights: Vec<f32>,             // weight of each clip when they all play
+    pub fade_time: f32,                // length of the cross-fades started from the UI
+    pub layers: Vec<Layer>,            // played on top of the selected clips, in order
+    pub machine: Option<StateMachine>, // picks the clips instead of the mode when set
+    pub rest: Pose,                    // the nodes before any clip moved them, see `Pose::rest`
+    mode: Mode,
+    fade: Option<Fade>,
+    pending: bool,        // a seek that hasn't been applied to the nodes yet
+    cursors: Vec<Cursor>, // one for each clip
+}
+
+// a clip fading out under the selected one
+#[derive(Debug, Copy, Clone)]
+struct Fade {
+    clip: usize,
+    playback: Playback, // the clip keeps playing while it fades
+    elapsed: f32,
+    duration: f32,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -28,37 +47,157 @@ impl PartialEq<usize> for Mode {
 impl Animations {
     pub fn new(inner: Vec<Animation>) -> Self {
         Self {
+            weights: vec![1.0; inner.len()],
             inner,
+            playback: Playback::new(0.0),
+            fade_time: 0.3,
+            layers: Vec::new(),
+            machine: None,
+            rest: Pose::default(),
             mode: Mode::None,
-            paused: true,
+            fade: None,
+            pending: false,
+            cursors: Vec::new(),
         }
     }
 
-    pub fn reset(&mut self) {
-        for anim in self.inner.iter_mut() {
-            anim.reset();
-        }
+    #[inline]
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Selects the clips to play, the playback length follows the longest of them.
+    pub fn set_mode(&mut self, mode: Mode) {
+        let duration = match mode {
+            Mode::All => self
+                .inner
+                .iter()
+                .map(Animation::duration)
+                .fold(0.0, f32::max),
+            Mode::None => 0.0,
+            Mode::Single(i) => self.inner[i].duration(),
+        };
+
+        self.mode = mode;
+        self.fade = None;
+        self.playback.set_duration(duration);
+        self.pending = true;
+    }
+
+    /// Switches to `clip` over `duration` seconds, the selected clip keeps playing as it fades
+    /// out. Without a single clip selected there's nothing to fade from and it switches at once.
+    pub fn cross_fade(&mut self, clip: usize, duration: f32) {
+        let from = match self.mode {
+            Mode::Single(from) if from != clip && duration > 0.0 => from,
+            _ => {
+                self.set_mode(Mode::Single(clip));
+                return;
+            }
+        };
+
+        let fading = Fade {
+            clip: from,
+            playback: self.playback,
+            elapsed: 0.0,
+            duration,
+        };
+
+        self.set_mode(Mode::Single(clip));
+        self.playback.rewind();
+        self.playback.playing = true;
+        self.fade = Some(fading);
     }
 
-    pub fn animate(&mut self, time: f32, nodes: &mut [super::Node]) -> bool {
-        if !self.paused {
-            match self.mode {
-                Mode::All => {
-                    for anim in self.inner.iter_mut() {
-                        anim.animate(time, nodes);
+    /// The pose of the selected clips at the current time.
+    pub fn pose(&mut self) -> Pose {
+        let time = self.playback.time();
+        self.cursors.resize(self.inner.len(), Cursor::default());
+
+        match self.mode {
+            Mode::All => {
+                let poses: Vec<Pose> = self
+                    .inner
+                    .iter()
+                    .zip(self.cursors.iter_mut())
+                    .map(|(a, cursor)| a.sample_with(time, cursor))
+                    .collect();
+                let weighted: Vec<(&Pose, f32)> =
+                    poses.iter().zip(self.weights.iter().copied()).collect();
+
+                Pose::mix(&weighted)
+            }
+            Mode::None => Pose::default(),
+            Mode::Single(i) => {
+                let pose = self.inner[i].sample_with(time, &mut self.cursors[i]);
+
+                match self.fade {
+                    Some(fade) => {
+                        let from = self.inner[fade.clip]
+                            .sample_with(fade.playback.time(), &mut self.cursors[fade.clip]);
+                        from.blend(&pose, fade.elapsed / fade.duration)
                     }
-                    true
-                }
-                Mode::None => false,
-                Mode::Single(i) => {
-                    self.inner[i].animate(time, nodes);
-                    true
+                    None => pose,
                 }
             }
-        } else {
-            false
         }
     }
+
+    /// Moves the clip time, the nodes are posed on the next `animate` even when paused.
+    pub fn seek(&mut self, time: f32) {
+        self.playback.seek(time);
+        self.pending = true;
+    }
+
+    /// Goes back to where the playback starts, the end when playing backwards.
+    pub fn reset(&mut self) {
+        self.playback.rewind();
+        self.pending = true;
+    }
+
+    /// Advances the playback by `delta` seconds and poses the nodes, returns whether they changed.
+    pub fn animate(&mut self, delta: f32, nodes: &mut [super::Node]) -> bool {
+        // the machine keeps its own clocks but pauses along with the main playback
+        if let (Some(machine), true) = (self.machine.as_mut(), self.playback.playing) {
+            machine.advance(delta, &self.inner);
+        }
+
+        let layers_playing = self.layers.iter().any(|layer| layer.playback.playing);
+        let idle = self.mode == Mode::None && self.layers.is_empty() && self.machine.is_none();
+        let playing = self.playback.playing || layers_playing;
+        if idle || !(playing || self.pending) {
+            return false;
+        }
+
+        self.playback.advance(delta);
+        self.pending = false;
+        for layer in self.layers.iter_mut() {
+            layer.playback.advance(delta);
+        }
+
+        if let Some(fade) = self.fade.as_mut() {
+            if self.playback.playing {
+                fade.playback.advance(delta);
+                fade.elapsed = (fade.elapsed + delta).min(fade.duration);
+            }
+        }
+
+        let base = match self.machine.as_ref() {
+            Some(machine) => machine.pose(&self.inner),
+            None => self.pose(),
+        };
+        let (inner, rest) = (&self.inner, &self.rest);
+        let pose = self.layers.iter_mut().fold(base, |pose, layer| {
+            let clip = &inner[layer.clip];
+            layer.apply(&pose, clip, rest)
+        });
+        pose.apply(nodes);
+
+        if self.fade.is_some_and(|fade| fade.elapsed >= fade.duration) {
+            self.fade = None;
+        }
+
+        true
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -66,6 +205,7 @@ pub struct Animation {
     rotations: Vec<Channel<Quaternion<f32>>>,
     translations: Vec<Channel<Vector3<f32>>>,
     scales: Vec<Channel<Vector3<f32>>>,
+    weights: Vec<Channel<Vec<f32>>>, // morph target weights
 
     pub name: String,
 }
@@ -77,18 +217,20 @@ struct Channel<T> {
     input: Vec<f32>,
     output: Vec<T>,
     interpolation: Interpolation,
+}
 
-    frame: FrameData,
+/// Where the last keyframe lookups in a clip landed, the next ones start from there.
+///
+/// Only a hint: any cursor samples the same pose, but each playback of a clip should keep its
+/// own to stay fast.
+#[derive(Debug, Clone, Default)]
+pub struct Cursor {
+    frames: Vec<usize>, // the previous keyframe of each channel, in sampling order
 }
 
+// the keyframes around a point in time
 #[derive(Debug, Copy, Clone, Default)]
 struct FrameData {
-    time_accum: f32, // total time since the animation started
-    curr_time: f32,  // time relative to the beginning and end of the animation
-
-    end_time: f32,   // last frame time
-    start_time: f32, // first frame time
-
     prev_index: usize, // index of the last frame
     next_index: usize, // index of the next frame
 
@@ -97,23 +239,34 @@ struct FrameData {
 
 impl FrameData {
     fn update(&mut self, time: f32, inputs: &[f32]) {
-        self.time_accum += time;
-        self.curr_time = (self.time_accum % self.end_time).max(self.start_time);
-
-        let mut index = 0;
-        for (i, win) in inputs.windows(2).enumerate() {
-            let previous = win[0];
-            let next = win[1];
-            if self.curr_time >= previous && self.curr_time < next {
-                index = i;
-                break;
-            }
+        let last = inputs.len() - 1;
+
+        // before the first keyframe and after the last one the closest value is held, a NaN
+        // time holds the first one
+        let before = time.is_nan() || time <= inputs[0];
+        if before || time >= inputs[last] {
+            let index = if before { 0 } else { last };
+            self.prev_index = index;
+            self.next_index = index;
+            self.interp = 0.0;
+            return;
         }
 
+        // playback mostly moves a little each frame, so the frame of the last lookup or the one
+        // after it usually holds the time, anything else is a binary search
+        let covers = |i: usize| i < last && inputs[i] <= time && time < inputs[i + 1];
+        let index = if covers(self.prev_index) {
+            self.prev_index
+        } else if covers(self.prev_index + 1) {
+            self.prev_index + 1
+        } else {
+            inputs.partition_point(|&input| input <= time) - 1
+        };
+
         let previous_time = inputs[index];
         let next_time = inputs[index + 1];
         let delta = next_time - previous_time;
-        let from_start = self.curr_time - previous_time;
+        let from_start = time - previous_time;
 
         self.prev_index = index;
         self.next_index = index + 1;
@@ -122,111 +275,201 @@ impl FrameData {
 }
 
 impl Animation {
-    pub fn new(anim: &gltf::Animation, buf: &[gltf::buffer::Data]) -> Self {
-        Self {
+    pub fn new(anim: &gltf::Animation, buf: &[gltf::buffer::Data]) -> Result<Self, LoaderError> {
+        Ok(Self {
             rotations: anim
                 .channels()
                 .filter(|ch| ch.target().property() == Property::Rotation)
-                .map(|ch| -> Channel<Quaternion<f32>> {
-                    Channel::<Quaternion<f32>>::new_rotation(ch, buf)
-                })
-                .collect(),
+                .map(|ch| Channel::<Quaternion<f32>>::new_rotation(ch, buf))
+                .collect::<Result<_, _>>()?,
             translations: anim
                 .channels()
                 .filter(|ch| ch.target().property() == Property::Translation)
                 .map(|ch| Channel::<Vector3<f32>>::new_trans(ch, buf))
-                .collect(),
+                .collect::<Result<_, _>>()?,
             scales: anim
                 .channels()
                 .filter(|ch| ch.target().property() == Property::Scale)
                 .map(|ch| Channel::<Vector3<f32>>::new_scale(ch, buf))
-                .collect(),
+                .collect::<Result<_, _>>()?,
+            weights: anim
+                .channels()
+                .filter(|ch| ch.target().property() == Property::MorphTargetWeights)
+                .map(|ch| Channel::<Vec<f32>>::new_weights(ch, buf))
+                .collect::<Result<_, _>>()?,
             name: anim.name().map_or(anim.index().to_string(), String::from),
-        }
+        })
     }
 
-    pub fn reset(&mut self) {
-        for rc in self.rotations.iter_mut() {
-            rc.reset();
-        }
-        for rt in self.translations.iter_mut() {
-            rt.reset();
-        }
-        for rs in self.scales.iter_mut() {
-            rs.reset();
+    /// Every channel of this animation as glTF sampler data.
+    pub(super) fn raw_channels(&self) -> impl Iterator<Item = RawChannel<'_>> {
+        self.translations
+            .iter()
+            .map(Channel::raw)
+            .chain(self.rotations.iter().map(Channel::raw))
+            .chain(self.scales.iter().map(Channel::raw))
+            .chain(self.weights.iter().map(Channel::raw))
+    }
+
+    /// Points the channels at new node indices, channels of nodes mapped to `None` are dropped.
+    pub(super) fn retarget(&mut self, map: &[Option<usize>]) {
+        retarget(&mut self.rotations, map);
+        retarget(&mut self.translations, map);
+        retarget(&mut self.scales, map);
+        retarget(&mut self.weights, map);
+    }
+
+    /// Time of the last keyframe, clip times go from zero up to it.
+    pub fn duration(&self) -> f32 {
+        let ends = self
+            .translations
+            .iter()
+            .map(Channel::end)
+            .chain(self.rotations.iter().map(Channel::end))
+            .chain(self.scales.iter().map(Channel::end))
+            .chain(self.weights.iter().map(Channel::end));
+
+        ends.fold(0.0, f32::max)
+    }
+
+    /// Values of every animated property at `time`, in seconds since the start of the clip.
+    pub fn sample(&self, time: f32) -> Pose {
+        self.sample_with(time, &mut Cursor::default())
+    }
+
+    /// Same as `sample`, starting the keyframe lookups where the last ones with `cursor` landed.
+    pub fn sample_with(&self, time: f32, cursor: &mut Cursor) -> Pose {
+        let lengths = [
+            self.translations.len(),
+            self.rotations.len(),
+            self.scales.len(),
+        ];
+        cursor
+            .frames
+            .resize(lengths.iter().sum::<usize>() + self.weights.len(), 0);
+
+        let (translations, frames) = cursor.frames.split_at_mut(lengths[0]);
+        let (rotations, frames) = frames.split_at_mut(lengths[1]);
+        let (scales, weights) = frames.split_at_mut(lengths[2]);
+
+        Pose {
+            translations: sample(&self.translations, time, translations),
+            rotations: sample(&self.rotations, time, rotations),
+            scales: sample(&self.scales, time, scales),
+            weights: sample(&self.weights, time, weights),
         }
     }
 
-    pub fn animate(&mut self, time: f32, nodes: &mut [super::Node]) {
-        self.translations
-            .iter_mut()
-            .map(|ch| ch.animate(time))
-            .for_each(|(index, t)| {
-                nodes[index].translation = t;
-                nodes[index].update()
-            });
-        self.scales
-            .iter_mut()
-            .map(|ch| ch.animate(time))
-            .for_each(|(index, t)| {
-                nodes[index].scale = t;
-                nodes[index].update()
-            });
-        self.rotations
-            .iter_mut()
-            .map(|ch| ch.animate(time))
-            .for_each(|(index, t)| {
-                nodes[index].rotation = t;
-                nodes[index].update()
-            });
+    /// Poses the nodes as they are at `time`.
+    pub fn animate(&self, time: f32, nodes: &mut [super::Node]) {
+        self.sample(time).apply(nodes);
     }
 }
 
-impl<T: Interpolate + Copy> Channel<T> {
-    fn animate(&mut self, t: f32) -> (usize, T) {
-        self.frame.update(t, &self.input);
+/// A channel laid out as a glTF sampler, used by the exporter.
+pub(super) struct RawChannel<'a> {
+    pub target: usize,
+    pub path: Property,
+    pub interpolation: Interpolation,
+    pub input: &'a [f32],
+    pub output: Vec<f32>, // flattened keyframe values, tangents included for cubic splines
+}
 
-        let i = self.frame.prev_index;
-        let j = self.frame.next_index;
+impl<T> Channel<T> {
+    fn end(&self) -> f32 {
+        self.input[self.input.len() - 1]
+    }
+}
+
+impl<T: Flatten> Channel<T> {
+    fn raw(&self) -> RawChannel<'_> {
+        let mut output = Vec::new();
+        for value in self.output.iter() {
+            value.flatten(&mut output);
+        }
+
+        RawChannel {
+            target: self.target,
+            path: self.path,
+            interpolation: self.interpolation,
+            input: &self.input,
+            output,
+        }
+    }
+}
+
+fn sample<T: Interpolate + Clone>(
+    channels: &[Channel<T>],
+    time: f32,
+    frames: &mut [usize],
+) -> Vec<(usize, T)> {
+    channels
+        .iter()
+        .zip(frames.iter_mut())
+        .map(|(ch, frame)| ch.sample(time, frame))
+        .collect()
+}
+
+impl<T: Interpolate + Clone> Channel<T> {
+    // `cursor` is the previous keyframe of the last lookup
+    fn sample(&self, time: f32, cursor: &mut usize) -> (usize, T) {
+        let mut frame = FrameData {
+            prev_index: *cursor,
+            ..FrameData::default()
+        };
+        frame.update(time, &self.input);
+        *cursor = frame.prev_index;
+
+        let i = frame.prev_index;
+        let j = frame.next_index;
+
+        // a held value, splines keep it between their tangents
+        if i == j {
+            let k = match self.interpolation {
+                Interpolation::CubicSpline => i * 3 + 1,
+                _ => i,
+            };
+            return (self.target, self.output[k].clone());
+        }
 
         match self.interpolation {
             Interpolation::Linear => {
-                let transform = self.output[i].linear(self.output[j], self.frame.interp);
+                let transform = self.output[i].linear(self.output[j].clone(), frame.interp);
                 (self.target, transform)
             }
             Interpolation::CubicSpline => {
                 let previous_values = [
-                    self.output[i * 3],
-                    self.output[i * 3 + 1],
-                    self.output[i * 3 + 2],
+                    self.output[i * 3].clone(),
+                    self.output[i * 3 + 1].clone(),
+                    self.output[i * 3 + 2].clone(),
                 ];
                 let next_values = [
-                    self.output[i * 3 + 3],
-                    self.output[i * 3 + 4],
-                    self.output[i * 3 + 5],
+                    self.output[i * 3 + 3].clone(),
+                    self.output[i * 3 + 4].clone(),
+                    self.output[i * 3 + 5].clone(),
                 ];
                 let t = Interpolate::cubic(
                     previous_values,
                     self.input[i],
                     next_values,
                     self.input[j],
-                    self.frame.interp,
+                    frame.interp,
                 );
 
                 (self.target, t)
             }
-            Interpolation::Step => (self.target, self.output[i]),
+            Interpolation::Step => (self.target, self.output[i].clone()),
         }
     }
 }
 
 impl<T> Channel<T> {
-    fn new_rotation(ch: gltfChannel, buf: &[gltf::buffer::Data]) -> Channel<Quaternion<f32>> {
+    fn new_rotation(
+        ch: gltfChannel,
+        buf: &[gltf::buffer::Data],
+    ) -> Result<Channel<Quaternion<f32>>, LoaderError> {
         let reader = ch.reader(|buffer| Some(&buf[buffer.index()]));
-        let target = ch.target().node().index();
-        let path = ch.target().property();
-
-        let input: Vec<f32> = reader.read_inputs().unwrap().collect();
+        let input = read_input(&ch, buf)?;
         let output: Vec<Quaternion<f32>> =
             reader.read_outputs().map_or(vec![], |output| match output {
                 ReadOutputs::Rotations(r) => r
@@ -235,93 +478,144 @@ impl<T> Channel<T> {
                     .collect(),
                 _ => vec![],
             });
-        let frame = FrameData {
-            interp: 0.0,
-            time_accum: 0.0,
-            curr_time: input[0],
-            end_time: input[input.len() - 1],
-            start_time: input[0],
-            prev_index: 0,
-            next_index: 1,
-        };
 
-        Channel {
-            target,
-            path,
-            input,
-            output,
-            interpolation: ch.sampler().interpolation(),
-            frame,
-        }
+        Channel::from_keyframes(&ch, input, output)
     }
 
-    fn new_scale(ch: gltfChannel, buf: &[gltf::buffer::Data]) -> Channel<Vector3<f32>> {
+    fn new_scale(
+        ch: gltfChannel,
+        buf: &[gltf::buffer::Data],
+    ) -> Result<Channel<Vector3<f32>>, LoaderError> {
         let reader = ch.reader(|buffer| Some(&buf[buffer.index()]));
-        let target = ch.target().node().index();
-        let path = ch.target().property();
-
-        let input: Vec<f32> = reader.read_inputs().unwrap().collect();
+        let input = read_input(&ch, buf)?;
         let output: Vec<Vector3<f32>> =
             reader.read_outputs().map_or(vec![], |output| match output {
                 ReadOutputs::Scales(s) => s.map(Vector3::from).collect(),
                 _ => vec![],
             });
-        let frame = FrameData {
-            interp: 0.0,
-            time_accum: 0.0,
-            curr_time: input[0],
-            end_time: input[input.len() - 1],
-            start_time: input[0],
-            prev_index: 0,
-            next_index: 1,
-        };
 
-        Channel {
-            target,
-            path,
-            input,
-            output,
-            interpolation: ch.sampler().interpolation(),
-            frame,
-        }
+        Channel::from_keyframes(&ch, input, output)
     }
-    fn new_trans(ch: gltfChannel, buf: &[gltf::buffer::Data]) -> Channel<Vector3<f32>> {
-        let reader = ch.reader(|buffer| Some(&buf[buffer.index()]));
-        let target = ch.target().node().index();
-        let path = ch.target().property();
 
-        let input: Vec<f32> = reader.read_inputs().unwrap().collect();
+    fn new_trans(
+        ch: gltfChannel,
+        buf: &[gltf::buffer::Data],
+    ) -> Result<Channel<Vector3<f32>>, LoaderError> {
+        let reader = ch.reader(|buffer| Some(&buf[buffer.index()]));
+        let input = read_input(&ch, buf)?;
         let output: Vec<Vector3<f32>> =
             reader.read_outputs().map_or(vec![], |output| match output {
                 ReadOutputs::Translations(ts) => ts.map(Vector3::from).collect(),
                 _ => vec![],
             });
-        let frame = FrameData {
-            interp: 0.0,
-            time_accum: 0.0,
-            curr_time: input[0],
-            end_time: input[input.len() - 1],
-            start_time: input[0],
-            prev_index: 0,
-            next_index: 1,
+
+        Channel::from_keyframes(&ch, input, output)
+    }
+
+    fn new_weights(
+        ch: gltfChannel,
+        buf: &[gltf::buffer::Data],
+    ) -> Result<Channel<Vec<f32>>, LoaderError> {
+        let reader = ch.reader(|buffer| Some(&buf[buffer.index()]));
+        let input = read_input(&ch, buf)?;
+        let flat: Vec<f32> = reader.read_outputs().map_or(vec![], |output| match output {
+            ReadOutputs::MorphTargetWeights(w) => w.into_f32().collect(),
+            _ => vec![],
+        });
+
+        // the weights of every target are packed together for each keyframe
+        let values_per_key = if ch.sampler().interpolation() == Interpolation::CubicSpline {
+            3
+        } else {
+            1
+        };
+        let targets = (flat.len() / (input.len() * values_per_key)).max(1);
+        let output = flat.chunks(targets).map(Vec::from).collect();
+
+        Channel::from_keyframes(&ch, input, output)
+    }
+
+    // checks there's a value for every keyframe, three with cubic splines
+    fn from_keyframes(
+        ch: &gltfChannel,
+        input: Vec<f32>,
+        output: Vec<T>,
+    ) -> Result<Self, LoaderError> {
+        let interpolation = ch.sampler().interpolation();
+        let expected = match interpolation {
+            Interpolation::CubicSpline => input.len() * 3,
+            _ => input.len(),
         };
 
-        Channel {
-            target,
-            path,
+        if output.len() != expected {
+            return Err(LoaderError::KeyframeCount {
+                animation: ch.animation().index(),
+                node: ch.target().node().index(),
+                accessor: ch.sampler().output().index(),
+                expected,
+                found: output.len(),
+            });
+        }
+
+        Ok(Channel {
+            target: ch.target().node().index(),
+            path: ch.target().property(),
             input,
             output,
-            interpolation: ch.sampler().interpolation(),
-            frame,
-        }
+            interpolation,
+        })
     }
+}
 
-    pub fn reset(&mut self) {
-        self.frame.time_accum = 0.0;
+fn retarget<T>(channels: &mut Vec<Channel<T>>, map: &[Option<usize>]) {
+    channels.retain(|ch| map[ch.target].is_some());
+    for ch in channels.iter_mut() {
+        ch.target = map[ch.target].unwrap();
+    }
+}
+
+// keyframe times of a channel, there has to be at least one
+fn read_input(ch: &gltfChannel, buf: &[gltf::buffer::Data]) -> Result<Vec<f32>, LoaderError> {
+    let reader = ch.reader(|buffer| Some(&buf[buffer.index()]));
+    let input: Vec<f32> = reader
+        .read_inputs()
+        .map_or(Vec::new(), |input| input.collect());
+
+    if input.is_empty() {
+        Err(LoaderError::MissingKeyframes {
+            animation: ch.animation().index(),
+            node: ch.target().node().index(),
+            accessor: ch.sampler().input().index(),
+        })
+    } else {
+        Ok(input)
+    }
+}
+
+/// Writes a keyframe value in the component order glTF uses.
+trait Flatten {
+    fn flatten(&self, out: &mut Vec<f32>);
+}
+
+impl Flatten for Vector3<f32> {
+    fn flatten(&self, out: &mut Vec<f32>) {
+        out.extend_from_slice(&[self.x, self.y, self.z]);
     }
 }
 
-trait Interpolate: Sized {
+impl Flatten for Quaternion<f32> {
+    fn flatten(&self, out: &mut Vec<f32>) {
+        out.extend_from_slice(&[self.v.x, self.v.y, self.v.z, self.s]);
+    }
+}
+
+impl Flatten for Vec<f32> {
+    fn flatten(&self, out: &mut Vec<f32>) {
+        out.extend_from_slice(self);
+    }
+}
+
+pub(super) trait Interpolate: Sized {
     fn linear(&self, other: Self, t: f32) -> Self;
     fn cubic(source: [Self; 3], stime: f32, target: [Self; 3], ttime: f32, t: f32) -> Self;
 }
@@ -362,3 +656,229 @@ impl Interpolate for Quaternion<f32> {
         ret.normalize()
     }
 }
+
+impl Interpolate for Vec<f32> {
+    fn linear(&self, other: Self, t: f32) -> Self {
+        self.iter()
+            .zip(other.iter())
+            .map(|(a, b)| a + (b - a) * t)
+            .collect()
+    }
+
+    fn cubic(source: [Self; 3], stime: f32, target: [Self; 3], ttime: f32, t: f32) -> Self {
+        (0..source[1].len())
+            .map(|i| {
+                let p0 = source[1][i];
+                let m0 = (ttime - stime) * source[2][i];
+                let p1 = target[1][i];
+                let m1 = (ttime - stime) * target[0][i];
+
+                (2.0 * t * t * t - 3.0 * t * t + 1.0) * p0
+                    + (t * t * t - 2.0 * t * t + t) * m0
+                    + (-2.0 * t * t * t + 3.0 * t * t) * p1
+                    + (t * t * t - t * t) * m1
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{test_util::test_asset, Mask, SceneData, Wrap};
+
+    #[test]
+    fn morph_weights() {
+        let path = test_asset("morph.gltf");
+        let mut data = SceneData::load(path).unwrap();
+
+        let targets = &data.meshes[0].primitives[0].targets;
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].positions[2], Vector3::new(0.0, 1.0, 0.0));
+        assert!(targets[0].normals.is_empty());
+
+        // the node inherits the mesh defaults
+        assert_eq!(data.meshes[0].weights, vec![0.5]);
+        assert_eq!(data.nodes[0].weights, vec![0.5]);
+
+        let anim = data.animations[0].clone();
+        anim.animate(0.25, &mut data.nodes);
+        assert_eq!(data.nodes[0].weights, vec![0.25]);
+    }
+
+    #[test]
+    fn sample_and_playback() {
+        let path = test_asset("morph.gltf");
+        let mut data = SceneData::load(path).unwrap();
+        let anim = &data.animations[0];
+        assert_eq!(anim.duration(), 1.0);
+
+        // sampling has no state, times out of the clip hold the closest keyframe
+        assert_eq!(anim.sample(0.75).weights, vec![(0, vec![0.75])]);
+        assert_eq!(anim.sample(0.25).weights, vec![(0, vec![0.25])]);
+        assert_eq!(anim.sample(3.0).weights, vec![(0, vec![1.0])]);
+        assert_eq!(anim.sample(-1.0).weights, vec![(0, vec![0.0])]);
+
+        let mut animations = Animations::new(data.animations.clone());
+        animations.set_mode(Mode::Single(0));
+
+        // selecting a clip poses the nodes once, then nothing moves until it plays
+        assert!(animations.animate(0.5, &mut data.nodes));
+        assert_eq!(data.nodes[0].weights, vec![0.0]);
+        assert!(!animations.animate(0.5, &mut data.nodes));
+
+        animations.playback.playing = true;
+        animations.playback.speed = 0.5;
+        assert!(animations.animate(0.5, &mut data.nodes));
+        assert_eq!(data.nodes[0].weights, vec![0.25]);
+
+        // a seek poses the nodes even when paused
+        animations.playback.playing = false;
+        animations.seek(0.5);
+        assert!(animations.animate(0.5, &mut data.nodes));
+        assert_eq!(data.nodes[0].weights, vec![0.5]);
+
+        animations.reset();
+        animations.animate(0.0, &mut data.nodes);
+        assert_eq!(data.nodes[0].weights, vec![0.0]);
+    }
+
+    #[test]
+    fn cross_fade() {
+        let path = test_asset("morph.gltf");
+        let mut data = SceneData::load(path).unwrap();
+
+        // the same clip played backwards
+        let mut lower = data.animations[0].clone();
+        lower.weights[0].output.reverse();
+
+        let mut animations = Animations::new(vec![data.animations[0].clone(), lower]);
+        animations.set_mode(Mode::Single(0));
+        animations.playback.wrap = Wrap::Clamp;
+        animations.playback.playing = true;
+        animations.animate(0.5, &mut data.nodes);
+        assert_eq!(data.nodes[0].weights, vec![0.5]);
+
+        // halfway through, the first clip is at 1 and the second at 0.5
+        animations.cross_fade(1, 1.0);
+        animations.animate(0.5, &mut data.nodes);
+        assert_eq!(data.nodes[0].weights, vec![0.75]);
+
+        animations.animate(0.5, &mut data.nodes);
+        assert_eq!(data.nodes[0].weights, vec![0.0]);
+        assert!(animations.fade.is_none());
+
+        // every clip at once, mixed by weight
+        animations.set_mode(Mode::All);
+        animations.weights = vec![1.0, 3.0];
+        animations.seek(0.25);
+        animations.animate(0.0, &mut data.nodes);
+        assert_eq!(data.nodes[0].weights, vec![0.625]);
+    }
+
+    #[test]
+    fn layers() {
+        let path = test_asset("morph.gltf");
+        let mut data = SceneData::load(path).unwrap();
+
+        let clip = &data.animations[0];
+        let mut additive = Layer::additive(0, clip);
+        additive.playback.playing = false;
+        additive.playback.seek(0.25);
+        let mut layer = Layer::new(0, clip);
+        layer.playback.playing = false;
+        layer.playback.seek(1.0);
+        layer.weight = 0.5;
+
+        let mut animations = Animations::new(vec![clip.clone()]);
+        animations.rest = Pose::rest(&data.nodes);
+        animations.set_mode(Mode::Single(0));
+        animations.seek(0.5);
+
+        // adds what the clip moved since its first frame
+        animations.layers = vec![additive];
+        animations.animate(0.0, &mut data.nodes);
+        assert_eq!(data.nodes[0].weights, vec![0.75]);
+
+        animations.layers = vec![layer.clone()];
+        animations.seek(0.5);
+        animations.animate(0.0, &mut data.nodes);
+        assert_eq!(data.nodes[0].weights, vec![0.75]);
+
+        // masked out
+        animations.layers[0].mask = Mask::Nodes(vec![]);
+        animations.seek(0.5);
+        animations.animate(0.0, &mut data.nodes);
+        assert_eq!(data.nodes[0].weights, vec![0.5]);
+
+        // with nothing below, the layers start from the rest pose on every frame
+        layer.playback.wrap = Wrap::Clamp;
+        layer.playback.playing = true;
+        animations.layers = vec![layer];
+        animations.set_mode(Mode::None);
+        for _ in 0..3 {
+            animations.animate(0.1, &mut data.nodes);
+            assert_eq!(data.nodes[0].weights, vec![0.75]);
+        }
+
+        let mut additive = Layer::additive(0, clip);
+        additive.playback.playing = false;
+        additive.playback.seek(0.25);
+        animations.layers = vec![additive];
+        for _ in 0..3 {
+            animations.seek(0.0);
+            animations.animate(0.1, &mut data.nodes);
+            assert_eq!(data.nodes[0].weights, vec![0.75]);
+        }
+    }
+
+    #[test]
+    fn keyframe_lookup() {
+        let inputs = [0.0, 1.0, 2.0, 2.0, 3.0, 5.0];
+        let mut frame = FrameData::default();
+
+        let mut check = |time: f32, prev: usize, next: usize, interp: f32| {
+            frame.update(time, &inputs);
+            assert_eq!(
+                (frame.prev_index, frame.next_index, frame.interp),
+                (prev, next, interp),
+                "at {}",
+                time
+            );
+        };
+
+        // forwards, backwards and jumping around
+        check(0.5, 0, 1, 0.5);
+        check(1.5, 1, 2, 0.5);
+        check(2.0, 3, 4, 0.0);
+        check(4.0, 4, 5, 0.5);
+        check(0.25, 0, 1, 0.25);
+        check(2.5, 3, 4, 0.5);
+        check(1.0, 1, 2, 0.0);
+
+        // held outside the keyframes
+        check(-1.0, 0, 0, 0.0);
+        check(6.0, 5, 5, 0.0);
+        check(4.5, 4, 5, 0.75);
+
+        // a NaN time holds the first keyframe
+        check(f32::NAN, 0, 0, 0.0);
+        check(0.5, 0, 1, 0.5);
+    }
+
+    #[test]
+    fn cursors() {
+        fn shared<T: Send + Sync>(_: &T) {}
+
+        let path = test_asset("morph.gltf");
+        let data = SceneData::load(path).unwrap();
+        let anim = &data.animations[0];
+        shared(anim);
+
+        // a cursor only changes where the lookups start
+        let mut cursor = Cursor::default();
+        for &time in [0.75, 0.25, 0.5, 3.0, 0.0, f32::NAN].iter() {
+            assert_eq!(anim.sample_with(time, &mut cursor), anim.sample(time));
+        }
+    }
+}
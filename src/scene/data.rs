@@ -0,0 +1,383 @@
+use crate::{aabb::Aabb, core::camera::Camera, ogl::material::Material};
+use cgmath::{prelude::*, Matrix4};
+
+use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+use super::{
+    animations::Animation,
+    loader::{self, LoaderError},
+    obj, ply,
+    skin::Skin,
+    Light, MeshData, Node,
+};
+
+use rayon::prelude::*;
+use std::path::Path;
+
+/// The CPU side of a `Scene`.
+///
+/// Everything a loader produces lives here, nothing in it touches OpenGL, so it can be
+/// created, inspected and modified without a context. Converting it into a `Scene` uploads
+/// the meshes and images to the GPU.
+#[derive(Debug, Clone)]
+pub struct SceneData {
+    pub nodes: Vec<Node>,        // all nodes
+    pub scenes: Vec<SceneRoots>, // the root nodes of each scene in the file
+    pub default_scene: usize,    // index of the scene shown when loaded
+
+    pub meshes: Vec<MeshData>,
+    pub images: Vec<gltf::image::Data>,
+    pub textures: Vec<TextureData>, // what the texture indices of the materials point to
+    pub materials: Vec<Material>,
+    pub skins: Vec<Skin>,
+    pub animations: Vec<Animation>,
+    pub cameras: Vec<Camera>, // in the local space of the nodes using them
+    pub lights: Vec<Light>,
+    pub variants: Vec<String>, // names of the material variants, see `PrimitiveData::variants`
+}
+
+/// One of the scenes in a file, glTF documents may hold several scenes that share nodes.
+#[derive(Debug, Clone, Default)]
+pub struct SceneRoots {
+    pub name: Option<String>,
+    pub roots: Vec<usize>, // indices of the roots
+}
+
+/// A texture of the file, one of the images and how it's sampled.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextureData {
+    pub image: usize,
+    pub sampler: Sampler,
+}
+
+/// Filtering and wrapping of a texture, filters left to the renderer are `None`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sampler {
+    pub mag_filter: Option<MagFilter>,
+    pub min_filter: Option<MinFilter>,
+    pub wrap_s: WrappingMode,
+    pub wrap_t: WrappingMode,
+}
+
+impl TextureData {
+    /// The image sampled with the default settings.
+    pub fn new(image: usize) -> Self {
+        Self {
+            image,
+            sampler: Sampler::default(),
+        }
+    }
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self {
+            mag_filter: None,
+            min_filter: None,
+            wrap_s: WrappingMode::Repeat,
+            wrap_t: WrappingMode::Repeat,
+        }
+    }
+}
+
+impl From<gltf::texture::Sampler<'_>> for Sampler {
+    fn from(sampler: gltf::texture::Sampler) -> Self {
+        Self {
+            mag_filter: sampler.mag_filter(),
+            min_filter: sampler.min_filter(),
+            wrap_s: sampler.wrap_s(),
+            wrap_t: sampler.wrap_t(),
+        }
+    }
+}
+
+impl SceneRoots {
+    pub fn new(name: Option<String>, roots: Vec<usize>) -> Self {
+        Self { name, roots }
+    }
+}
+
+impl SceneData {
+    /// Loads a model file, the format is chosen by the file extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, LoaderError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase);
+
+        match extension.as_deref() {
+            Some("gltf") | Some("glb") => loader::load_gltf(path),
+            Some("obj") => obj::load_obj(path),
+            Some("ply") => ply::load_ply(path),
+            _ => Err(LoaderError::FileError(format!(
+                "unsupported model format: {}",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Loads several model files at once, in parallel, the results keep the order of `paths`.
+    pub fn load_many<P: AsRef<Path> + Sync>(paths: &[P]) -> Vec<Result<Self, LoaderError>> {
+        paths.par_iter().map(SceneData::load).collect()
+    }
+
+    /// Roots of the default scene.
+    pub fn roots(&self) -> &[usize] {
+        self.scenes
+            .get(self.default_scene)
+            .map_or(&[], |scene| &scene.roots)
+    }
+
+    /// Bounding box of the default scene in the nodes' rest pose.
+    pub fn aabb(&self) -> Aabb {
+        let bounds: Vec<Aabb> = self.meshes.iter().map(|m| m.aabb).collect();
+
+        self.roots().iter().fold(Aabb::default(), |aabb, &root| {
+            let root_aabb = self.nodes[root].gen_aabb(&self.nodes, &bounds, Matrix4::identity());
+            aabb.surrounds(&root_aabb)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::test_util::test_asset;
+
+    #[test]
+    fn load_without_context() {
+        let path = test_asset("triangle.gltf");
+        let data = SceneData::load(path).unwrap();
+
+        assert_eq!(data.nodes.len(), 2);
+        assert_eq!(data.roots(), &[0]);
+        assert_eq!(data.nodes[0].children, vec![1]);
+        assert_eq!(data.nodes[1].mesh, Some(0));
+        assert_eq!(data.materials.len(), 1);
+
+        let prim = &data.meshes[0].primitives[0];
+        assert_eq!(prim.vertices.len(), 3);
+        assert_eq!(prim.indices, vec![0, 1, 2]);
+        assert_eq!(prim.material, Some(0));
+        assert_eq!(prim.mode, gl::TRIANGLES);
+
+        let aabb = data.aabb();
+        assert_eq!(aabb.min, cgmath::vec3(1.0, 0.0, 0.0));
+        assert_eq!(aabb.max, cgmath::vec3(2.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn multiple_scenes() {
+        let path = test_asset("multi_scene.gltf");
+        let data = SceneData::load(path).unwrap();
+
+        assert_eq!(data.scenes.len(), 2);
+        assert_eq!(data.scenes[0].name.as_deref(), Some("First"));
+        assert_eq!(data.scenes[0].roots, vec![0]);
+        assert_eq!(data.default_scene, 1);
+        assert_eq!(data.roots(), &[1, 2]);
+
+        let aabb = data.aabb();
+        assert_eq!(aabb.min, cgmath::vec3(0.0, 0.0, -2.0));
+        assert_eq!(aabb.max, cgmath::vec3(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn imported_cameras() {
+        use crate::core::camera::Projection;
+
+        let path = test_asset("cameras.gltf");
+        let data = SceneData::load(path).unwrap();
+
+        assert_eq!(data.cameras.len(), 2);
+        assert_eq!(data.nodes[0].camera, Some(0));
+        assert_eq!(data.nodes[1].camera, Some(1));
+
+        let persp = data.cameras[0];
+        assert!((persp.fov - 45.0).abs() < 1e-4);
+        assert_eq!(
+            persp.projection,
+            Projection::Perspective {
+                aspect_ratio: None,
+                znear: 0.05,
+                zfar: None
+            }
+        );
+        assert_eq!(
+            data.cameras[1].projection,
+            Projection::Orthographic {
+                xmag: 2.0,
+                ymag: 1.5,
+                znear: 0.1,
+                zfar: 50.0
+            }
+        );
+
+        let placed = persp.transformed(data.nodes[0].transform);
+        assert_eq!(placed.pos, cgmath::Point3::new(0.0, 0.0, 5.0));
+        assert_eq!(placed.front, cgmath::vec3(0.0, 0.0, -1.0));
+
+        let turned = data.cameras[1].transformed(data.nodes[1].transform);
+        assert!((turned.front - cgmath::vec3(-1.0, 0.0, 0.0)).magnitude() < 1e-5);
+        assert!((turned.up - cgmath::vec3(0.0, 1.0, 0.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn second_uv_set_and_colors() {
+        use crate::scene::Attributes;
+
+        let path = test_asset("uv_sets.gltf");
+        let data = SceneData::load(path).unwrap();
+        let prim = &data.meshes[0].primitives[0];
+
+        let expected = Attributes::TEX_COORD_0 | Attributes::TEX_COORD_1 | Attributes::COLOR;
+        assert!(prim.attributes.contains(expected));
+        assert!(!prim.attributes.contains(Attributes::TANGENT));
+        // generated, the file has none
+        assert!(prim.attributes.contains(Attributes::NORMAL));
+
+        assert_eq!(prim.vertices[1].tex1, cgmath::vec2(1.0, 0.5));
+        assert_eq!(prim.vertices[2].color, cgmath::vec4(0.0, 0.0, 1.0, 0.5));
+
+        let material = data.materials[0];
+        assert_eq!(material.base_tex_coord, 1);
+        assert_eq!(material.occlusion_tex_coord, 0);
+    }
+
+    #[test]
+    fn quantized_attributes() {
+        let path = test_asset("quantized.gltf");
+        let data = SceneData::load(path).unwrap();
+        let prim = &data.meshes[0].primitives[0];
+        let v = &prim.vertices;
+
+        assert_eq!(v[1].pos, cgmath::vec3(1.0, 0.0, 0.0));
+        assert_eq!(v[2].pos, cgmath::vec3(0.0, 1.0, -1.0));
+        assert_eq!(v[2].normal, cgmath::vec3(0.0, -1.0, 0.0));
+        assert_eq!(v[1].tex, cgmath::vec2(1.0, 0.0));
+        assert!((v[2].tex.y - 0.5).abs() < 1e-4);
+        assert_eq!(v[2].tex1, cgmath::vec2(0.0, -3.0));
+        assert_eq!(prim.targets[0].positions[2], cgmath::vec3(-1.0, 0.0, 0.0));
+
+        assert_eq!(prim.aabb.min, cgmath::vec3(0.0, 0.0, -1.0));
+        assert_eq!(prim.aabb.max, cgmath::vec3(1.0, 1.0, 0.0));
+
+        let transform = data.materials[0].base_tex_transform;
+        assert_eq!(transform.offset, cgmath::vec2(0.5, 0.0));
+        assert_eq!(transform.scale, cgmath::vec2(2.0, 1.0));
+        assert!((transform.rotation - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+
+        // gltf leaves these two to the raw JSON, the transform's texCoord wins over the slot's
+        let mat = &data.materials[0];
+        assert_eq!(mat.normal_tex_transform.offset, cgmath::vec2(0.0, 0.25));
+        assert_eq!(mat.normal_tex_transform.scale, cgmath::vec2(0.5, 0.5));
+        assert_eq!(mat.normal_tex_coord, 1);
+        assert_eq!(mat.occlusion_tex_transform.rotation, 0.5);
+        assert_eq!(mat.occlusion_tex_transform.scale, cgmath::vec2(1.0, 1.0));
+        assert_eq!(mat.occlusion_tex_coord, 1);
+        assert_eq!(mat.base_tex_coord, 0);
+    }
+
+    #[test]
+    fn material_variants() {
+        let path = test_asset("variants.gltf");
+        let data = SceneData::load(path).unwrap();
+        assert_eq!(data.variants, vec!["Red", "Blue", "Plain"]);
+
+        let (mapped, unmapped) = (&data.meshes[0].primitives[0], &data.meshes[0].primitives[1]);
+        assert_eq!(mapped.variants, vec![(0, 1), (1, 2)]);
+        assert_eq!(mapped.variant_material(Some(1)), Some(2));
+        // no mapping for the variant, the default material stays
+        assert_eq!(mapped.variant_material(Some(2)), Some(0));
+        assert_eq!(mapped.variant_material(None), Some(0));
+        assert_eq!(unmapped.variant_material(Some(0)), Some(0));
+    }
+
+    #[test]
+    fn gpu_instancing() {
+        let path = test_asset("instancing.gltf");
+        let data = SceneData::load(path).unwrap();
+        let instances = &data.nodes[0].instances;
+        assert_eq!(instances.len(), 3);
+
+        let point = |i: usize, x, y, z| {
+            let p = instances[i] * cgmath::vec4(x, y, z, 1.0);
+            cgmath::vec3(p.x, p.y, p.z)
+        };
+        assert_eq!(point(0, 1.0, 0.0, 0.0), cgmath::vec3(1.0, 0.0, 0.0));
+        // rotated 90 degrees around Z
+        assert!((point(1, 1.0, 0.0, 0.0) - cgmath::vec3(5.0, 1.0, 0.0)).magnitude() < 1e-4);
+        assert_eq!(point(2, 1.0, 0.0, 0.0), cgmath::vec3(2.0, 0.0, -5.0));
+
+        // the bounds cover every instance
+        let aabb = data.aabb();
+        assert_eq!(aabb.min.z, -5.0);
+        assert_eq!(aabb.max.y, 2.0);
+    }
+
+    #[test]
+    fn default_bind_matrices() {
+        let path = test_asset("skin.gltf");
+        let mut data = SceneData::load(path).unwrap();
+
+        assert_eq!(data.skins[0].joints[0].node, 1);
+        assert_eq!(data.nodes[1].name.as_deref(), Some("Joint"));
+        assert_eq!(data.skins[0].joints[0].bind_matrix, Matrix4::identity());
+
+        // a single keyframe holds its value
+        let anim = data.animations[0].clone();
+        anim.animate(0.5, &mut data.nodes);
+        assert!((data.nodes[1].rotation.v.z - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn malformed_files() {
+        let load = |name: &str| SceneData::load(test_asset(name)).unwrap_err();
+
+        assert!(matches!(
+            load("bad_keyframes.gltf"),
+            LoaderError::KeyframeCount {
+                animation: 0,
+                node: 0,
+                accessor: 2,
+                expected: 2,
+                found: 1
+            }
+        ));
+        assert!(matches!(
+            load("bad_indices.gltf"),
+            LoaderError::IndexOutOfRange {
+                mesh: 0,
+                primitive: 0,
+                accessor: 1,
+                index: 3,
+                count: 3
+            }
+        ));
+        assert!(matches!(
+            load("bad_variants.gltf"),
+            LoaderError::VariantMapping {
+                mesh: 0,
+                primitive: 0,
+                variant: 1,
+                material: 99
+            }
+        ));
+    }
+
+    #[test]
+    fn load_many() {
+        let paths = [
+            test_asset("triangle.gltf"),
+            test_asset("missing.gltf"),
+            test_asset("quad.obj"),
+        ];
+
+        let loaded = SceneData::load_many(&paths);
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(loaded[0].as_ref().unwrap().nodes.len(), 2);
+        assert!(loaded[1].is_err());
+        assert!(loaded[2].is_ok());
+    }
+}
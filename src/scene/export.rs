@@ -0,0 +1,1186 @@
+use cgmath::{Deg, Matrix4, Rad};
+use gltf::{
+    animation::Property,
+    json::{
+        self, accessor::ComponentType, accessor::GenericComponentType, accessor::Type,
+        buffer::Target, validation::Checked::Valid, Index, Value,
+    },
+};
+
+use crate::{
+    core::camera::{Camera, Projection},
+    ogl::material::{AlphaMode, Material, TextureTransform},
+};
+
+use super::{
+    data::{Sampler, SceneData},
+    loader::LoaderError,
+    node::{self, Node},
+    Attributes, Light, LightKind, MeshData, PrimitiveData,
+};
+
+use std::{collections::HashMap, fs, path::Path};
+
+impl SceneData {
+    /// Writes the scene as a `.gltf` file plus a `.bin` file with the same name next to it.
+    pub fn save_gltf<P: AsRef<Path>>(&self, path: P) -> Result<(), LoaderError> {
+        let path = path.as_ref();
+        let (mut document, bin) = Exporter::export(self)?;
+
+        let buffer = document.get_mut("buffers").and_then(|b| b.get_mut(0));
+        if let Some(buffer) = buffer {
+            let bin_path = path.with_extension("bin");
+            let uri = bin_path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(String::from)
+                .ok_or_else(|| LoaderError::FileError(format!("invalid path: {:?}", path)))?;
+
+            buffer["uri"] = Value::from(uri);
+            fs::write(bin_path, &bin)?;
+        }
+
+        fs::write(path, to_json(&document)?)?;
+        Ok(())
+    }
+
+    /// Writes the scene as a single binary glTF file.
+    pub fn save_glb<P: AsRef<Path>>(&self, path: P) -> Result<(), LoaderError> {
+        let (document, mut bin) = Exporter::export(self)?;
+        let json = to_json(&document)?;
+
+        align(&mut bin);
+        let glb = gltf::binary::Glb {
+            header: gltf::binary::Header {
+                magic: *b"glTF",
+                version: 2,
+                length: 0, // computed by the writer
+            },
+            json: json.into(),
+            bin: if bin.is_empty() {
+                None
+            } else {
+                Some(bin.into())
+            },
+        };
+
+        glb.to_writer(fs::File::create(path)?)?;
+        Ok(())
+    }
+}
+
+fn to_json(document: &Value) -> Result<Vec<u8>, LoaderError> {
+    json::serialize::to_vec_pretty(document).map_err(serialize_error)
+}
+
+fn serialize_error(e: json::Error) -> LoaderError {
+    LoaderError::FileError(format!("couldn't serialize the document: {}", e))
+}
+
+#[inline]
+fn align(bin: &mut Vec<u8>) {
+    let aligned = (bin.len() + 3) & !3;
+    bin.resize(aligned, 0);
+}
+
+fn push<T>(vec: &mut Vec<T>, item: T) -> Index<T> {
+    vec.push(item);
+    Index::new(vec.len() as u32 - 1)
+}
+
+/// Builds the glTF document and its single binary buffer.
+struct Exporter {
+    root: json::Root,
+    bin: Vec<u8>,
+    // extensions gltf can't write, (JSON pointer of the object, extension, value)
+    raw: Vec<(String, &'static str, Value)>,
+}
+
+impl Exporter {
+    fn export(data: &SceneData) -> Result<(Value, Vec<u8>), LoaderError> {
+        let mut this = Exporter {
+            root: json::Root::default(),
+            bin: Vec::new(),
+            raw: Vec::new(),
+        };
+
+        this.root.asset = json::Asset {
+            copyright: None,
+            extensions: None,
+            extras: Default::default(),
+            generator: Some(String::from("glboot")),
+            min_version: None,
+            version: String::from("2.0"),
+        };
+
+        for image in data.images.iter() {
+            this.image(image)?;
+        }
+
+        // textures keep their indices, the materials point to them
+        let mut samplers = Vec::new();
+        for texture in data.textures.iter() {
+            let sampler = match samplers.iter().position(|s| *s == texture.sampler) {
+                Some(index) => index,
+                None => {
+                    samplers.push(texture.sampler);
+                    this.root.samplers.push(sampler(&texture.sampler));
+                    samplers.len() - 1
+                }
+            };
+
+            this.root.textures.push(json::Texture {
+                name: None,
+                sampler: Some(Index::new(sampler as u32)),
+                source: Index::new(texture.image as u32),
+                extensions: None,
+                extras: Default::default(),
+            });
+        }
+
+        this.root.materials = data.materials.iter().map(material).collect();
+        for (i, mat) in data.materials.iter().enumerate() {
+            for (name, extension) in material_extensions(mat) {
+                this.raw
+                    .push((format!("/materials/{}", i), name, extension));
+            }
+        }
+
+        let materials = &data.materials;
+        let extensions_used = &mut this.root.extensions_used;
+        if materials.iter().any(has_texture_transform) {
+            extensions_used.push(String::from("KHR_texture_transform"));
+        }
+        if materials.iter().any(|m| m.transmission.is_some()) {
+            extensions_used.push(String::from("KHR_materials_transmission"));
+        }
+        if materials.iter().any(|m| m.ior != 1.5) {
+            extensions_used.push(String::from("KHR_materials_ior"));
+        }
+        if materials.iter().any(|m| m.unlit) {
+            extensions_used.push(String::from("KHR_materials_unlit"));
+        }
+
+        for (m, mesh) in data.meshes.iter().enumerate() {
+            let json_mesh = this.mesh(mesh);
+            this.root.meshes.push(json_mesh);
+
+            for (p, prim) in mesh.primitives.iter().enumerate() {
+                if !prim.variants.is_empty() {
+                    let pointer = format!("/meshes/{}/primitives/{}", m, p);
+                    let mappings = variant_mappings(&prim.variants);
+                    this.raw.push((pointer, "KHR_materials_variants", mappings));
+                }
+            }
+        }
+
+        if !data.variants.is_empty() {
+            let mut ext = Value::Object(Default::default());
+            ext["variants"] = data
+                .variants
+                .iter()
+                .map(|name| {
+                    let mut variant = Value::Object(Default::default());
+                    variant["name"] = Value::from(name.as_str());
+                    variant
+                })
+                .collect();
+            this.raw
+                .push((String::new(), "KHR_materials_variants", ext));
+        }
+
+        this.root.cameras = data.cameras.iter().map(camera).collect();
+        this.lights(&data.lights);
+        this.root.nodes = data.nodes.iter().map(node).collect();
+        for (i, node) in data.nodes.iter().enumerate() {
+            if !node.instances.is_empty() {
+                let instancing = this.instancing(&node.instances);
+                this.raw.push((
+                    format!("/nodes/{}", i),
+                    "EXT_mesh_gpu_instancing",
+                    instancing,
+                ));
+            }
+        }
+
+        for skin in data.skins.iter() {
+            let matrices: Vec<f32> = skin
+                .joints
+                .iter()
+                .flat_map(|joint| {
+                    let m: &[f32; 16] = joint.bind_matrix.as_ref();
+                    m.to_vec()
+                })
+                .collect();
+            let inverse_bind_matrices = this.floats(&matrices, Type::Mat4, None, false);
+
+            this.root.skins.push(json::Skin {
+                extensions: None,
+                extras: Default::default(),
+                inverse_bind_matrices: Some(inverse_bind_matrices),
+                joints: skin
+                    .joints
+                    .iter()
+                    .map(|joint| Index::new(joint.node as u32))
+                    .collect(),
+                name: None,
+                skeleton: None,
+            });
+        }
+
+        for anim in data.animations.iter() {
+            let mut animation = json::Animation {
+                extensions: None,
+                extras: Default::default(),
+                channels: Vec::new(),
+                name: Some(anim.name.clone()),
+                samplers: Vec::new(),
+            };
+
+            for channel in anim.raw_channels() {
+                let ty = match channel.path {
+                    Property::Rotation => Type::Vec4,
+                    Property::Translation | Property::Scale => Type::Vec3,
+                    Property::MorphTargetWeights => Type::Scalar,
+                };
+                let input = this.floats(channel.input, Type::Scalar, None, true);
+                let output = this.floats(&channel.output, ty, None, false);
+
+                let sampler = push(
+                    &mut animation.samplers,
+                    json::animation::Sampler {
+                        extensions: None,
+                        extras: Default::default(),
+                        input,
+                        interpolation: Valid(channel.interpolation),
+                        output,
+                    },
+                );
+
+                animation.channels.push(json::animation::Channel {
+                    sampler,
+                    target: json::animation::Target {
+                        extensions: None,
+                        extras: Default::default(),
+                        node: Index::new(channel.target as u32),
+                        path: Valid(channel.path),
+                    },
+                    extensions: None,
+                    extras: Default::default(),
+                });
+            }
+
+            this.root.animations.push(animation);
+        }
+
+        this.root.scenes = data
+            .scenes
+            .iter()
+            .map(|scene| json::Scene {
+                extensions: None,
+                extras: Default::default(),
+                name: scene.name.clone(),
+                nodes: scene
+                    .roots
+                    .iter()
+                    .map(|&root| Index::new(root as u32))
+                    .collect(),
+            })
+            .collect();
+
+        if !this.root.scenes.is_empty() {
+            this.root.scene = Some(Index::new(data.default_scene as u32));
+        }
+
+        if !this.bin.is_empty() {
+            align(&mut this.bin);
+            this.root.buffers.push(json::Buffer {
+                byte_length: this.bin.len() as u32,
+                name: None,
+                uri: None,
+                extensions: None,
+                extras: Default::default(),
+            });
+        }
+
+        let document = json::serialize::to_value(&this.root).map_err(serialize_error)?;
+        Ok((with_extensions(document, this.raw), this.bin))
+    }
+
+    fn view(&mut self, bytes: &[u8], target: Option<Target>) -> Index<json::buffer::View> {
+        align(&mut self.bin);
+
+        let view = json::buffer::View {
+            buffer: Index::new(0),
+            byte_length: bytes.len() as u32,
+            byte_offset: Some(self.bin.len() as u32),
+            byte_stride: None,
+            name: None,
+            target: target.map(Valid),
+            extensions: None,
+            extras: Default::default(),
+        };
+
+        self.bin.extend_from_slice(bytes);
+        push(&mut self.root.buffer_views, view)
+    }
+
+    fn accessor(
+        &mut self,
+        bytes: &[u8],
+        count: usize,
+        component: ComponentType,
+        ty: Type,
+        target: Option<Target>,
+    ) -> Index<json::Accessor> {
+        let view = self.view(bytes, target);
+
+        push(
+            &mut self.root.accessors,
+            json::Accessor {
+                buffer_view: Some(view),
+                byte_offset: 0,
+                count: count as u32,
+                component_type: Valid(GenericComponentType(component)),
+                extensions: None,
+                extras: Default::default(),
+                type_: Valid(ty),
+                min: None,
+                max: None,
+                name: None,
+                normalized: false,
+                sparse: None,
+            },
+        )
+    }
+
+    /// Float accessor, `bounds` also stores the min and max of each component.
+    fn floats(
+        &mut self,
+        data: &[f32],
+        ty: Type,
+        target: Option<Target>,
+        bounds: bool,
+    ) -> Index<json::Accessor> {
+        let components = ty.multiplicity();
+        let bytes: Vec<u8> = data.iter().flat_map(|f| f.to_le_bytes().to_vec()).collect();
+        let index = self.accessor(
+            &bytes,
+            data.len() / components,
+            ComponentType::F32,
+            ty,
+            target,
+        );
+
+        if bounds && !data.is_empty() {
+            let mut min = vec![f32::MAX; components];
+            let mut max = vec![f32::MIN; components];
+
+            for value in data.chunks(components) {
+                for (i, &c) in value.iter().enumerate() {
+                    min[i] = min[i].min(c);
+                    max[i] = max[i].max(c);
+                }
+            }
+
+            let accessor = &mut self.root.accessors[index.value()];
+            accessor.min = json::serialize::to_value(min).ok();
+            accessor.max = json::serialize::to_value(max).ok();
+        }
+
+        index
+    }
+
+    fn image(&mut self, image: &gltf::image::Data) -> Result<(), LoaderError> {
+        use gltf::image::Format;
+        use image::ColorType;
+
+        let mut pixels = image.pixels.clone();
+        let color = match image.format {
+            Format::R8 => ColorType::L8,
+            Format::R8G8 => ColorType::La8,
+            Format::R8G8B8 => ColorType::Rgb8,
+            Format::R8G8B8A8 => ColorType::Rgba8,
+            Format::B8G8R8 => {
+                pixels.chunks_mut(3).for_each(|p| p.swap(0, 2));
+                ColorType::Rgb8
+            }
+            Format::B8G8R8A8 => {
+                pixels.chunks_mut(4).for_each(|p| p.swap(0, 2));
+                ColorType::Rgba8
+            }
+            Format::R16 => ColorType::L16,
+            Format::R16G16 => ColorType::La16,
+            Format::R16G16B16 => ColorType::Rgb16,
+            Format::R16G16B16A16 => ColorType::Rgba16,
+        };
+
+        // PNG stores 16 bit samples as big endian
+        if let ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 = color {
+            for sample in pixels.chunks_mut(2) {
+                let value = u16::from_ne_bytes([sample[0], sample[1]]);
+                sample.copy_from_slice(&value.to_be_bytes());
+            }
+        }
+
+        let mut png = Vec::new();
+        image::codecs::png::PngEncoder::new(&mut png).encode(
+            &pixels,
+            image.width,
+            image.height,
+            color,
+        )?;
+
+        let view = self.view(&png, None);
+        self.root.images.push(json::Image {
+            buffer_view: Some(view),
+            mime_type: Some(json::image::MimeType(String::from("image/png"))),
+            name: None,
+            uri: None,
+            extensions: None,
+            extras: Default::default(),
+        });
+
+        Ok(())
+    }
+
+    fn mesh(&mut self, mesh: &MeshData) -> json::Mesh {
+        json::Mesh {
+            extensions: None,
+            extras: Default::default(),
+            name: mesh.name.clone(),
+            primitives: mesh
+                .primitives
+                .iter()
+                .map(|prim| self.primitive(prim))
+                .collect(),
+            weights: if mesh.weights.is_empty() {
+                None
+            } else {
+                Some(mesh.weights.clone())
+            },
+        }
+    }
+
+    fn primitive(&mut self, prim: &PrimitiveData) -> json::mesh::Primitive {
+        use json::mesh::Semantic;
+
+        let vertices = &prim.vertices;
+        let array = Some(Target::ArrayBuffer);
+        let mut attributes = HashMap::new();
+
+        let present = |attribute| prim.attributes.contains(attribute);
+        let positions: Vec<f32> = vertices
+            .iter()
+            .flat_map(|v| vec![v.pos.x, v.pos.y, v.pos.z])
+            .collect();
+        let positions = self.floats(&positions, Type::Vec3, array, true);
+        attributes.insert(Valid(Semantic::Positions), positions);
+
+        if present(Attributes::NORMAL) {
+            let normals: Vec<f32> = vertices
+                .iter()
+                .flat_map(|v| vec![v.normal.x, v.normal.y, v.normal.z])
+                .collect();
+            let normals = self.floats(&normals, Type::Vec3, array, false);
+            attributes.insert(Valid(Semantic::Normals), normals);
+        }
+
+        if present(Attributes::TEX_COORD_0) {
+            let coords: Vec<f32> = vertices
+                .iter()
+                .flat_map(|v| vec![v.tex.x, v.tex.y])
+                .collect();
+            let coords = self.floats(&coords, Type::Vec2, array, false);
+            attributes.insert(Valid(Semantic::TexCoords(0)), coords);
+        }
+
+        if present(Attributes::TEX_COORD_1) {
+            let coords: Vec<f32> = vertices
+                .iter()
+                .flat_map(|v| vec![v.tex1.x, v.tex1.y])
+                .collect();
+            let coords = self.floats(&coords, Type::Vec2, array, false);
+            attributes.insert(Valid(Semantic::TexCoords(1)), coords);
+        }
+
+        if present(Attributes::TANGENT) {
+            let tangents: Vec<f32> = vertices
+                .iter()
+                .flat_map(|v| vec![v.tangent.x, v.tangent.y, v.tangent.z, v.tangent.w])
+                .collect();
+            let tangents = self.floats(&tangents, Type::Vec4, array, false);
+            attributes.insert(Valid(Semantic::Tangents), tangents);
+        }
+
+        if present(Attributes::COLOR) {
+            let colors: Vec<f32> = vertices
+                .iter()
+                .flat_map(|v| vec![v.color.x, v.color.y, v.color.z, v.color.w])
+                .collect();
+            let colors = self.floats(&colors, Type::Vec4, array, false);
+            attributes.insert(Valid(Semantic::Colors(0)), colors);
+        }
+
+        if present(Attributes::SKIN) {
+            let joints: Vec<u8> = vertices
+                .iter()
+                .flat_map(|v| {
+                    let j: &[f32; 4] = v.joints.as_ref();
+                    j.iter()
+                        .flat_map(|&j| (j as u16).to_le_bytes().to_vec())
+                        .collect::<Vec<u8>>()
+                })
+                .collect();
+            let joints = self.accessor(
+                &joints,
+                vertices.len(),
+                ComponentType::U16,
+                Type::Vec4,
+                array,
+            );
+            attributes.insert(Valid(Semantic::Joints(0)), joints);
+
+            let weights: Vec<f32> = vertices
+                .iter()
+                .flat_map(|v| vec![v.weights.x, v.weights.y, v.weights.z, v.weights.w])
+                .collect();
+            let weights = self.floats(&weights, Type::Vec4, array, false);
+            attributes.insert(Valid(Semantic::Weights(0)), weights);
+        }
+
+        let indices = if prim.indices.is_empty() {
+            None
+        } else {
+            let bytes: Vec<u8> = prim
+                .indices
+                .iter()
+                .flat_map(|i| i.to_le_bytes().to_vec())
+                .collect();
+
+            Some(self.accessor(
+                &bytes,
+                prim.indices.len(),
+                ComponentType::U32,
+                Type::Scalar,
+                Some(Target::ElementArrayBuffer),
+            ))
+        };
+
+        let targets: Vec<json::mesh::MorphTarget> = prim
+            .targets
+            .iter()
+            .map(|target| {
+                let mut displacements = |data: &[cgmath::Vector3<f32>], bounds| {
+                    if data.is_empty() {
+                        None
+                    } else {
+                        let flat: Vec<f32> =
+                            data.iter().flat_map(|d| vec![d.x, d.y, d.z]).collect();
+                        Some(self.floats(&flat, Type::Vec3, array, bounds))
+                    }
+                };
+
+                json::mesh::MorphTarget {
+                    positions: displacements(&target.positions, true),
+                    normals: displacements(&target.normals, false),
+                    tangents: displacements(&target.tangents, false),
+                }
+            })
+            .collect();
+
+        json::mesh::Primitive {
+            attributes,
+            extensions: None,
+            extras: Default::default(),
+            indices,
+            material: prim.material.map(|m| Index::new(m as u32)),
+            mode: Valid(mode(prim.mode)),
+            targets: if targets.is_empty() {
+                None
+            } else {
+                Some(targets)
+            },
+        }
+    }
+
+    // EXT_mesh_gpu_instancing, one TRS accessor each
+    fn instancing(&mut self, instances: &[Matrix4<f32>]) -> Value {
+        let (mut translations, mut rotations, mut scales) = (Vec::new(), Vec::new(), Vec::new());
+        for &instance in instances.iter() {
+            let (t, r, s) = node::decompose(instance);
+            translations.extend_from_slice(&[t.x, t.y, t.z]);
+            rotations.extend_from_slice(&[r.v.x, r.v.y, r.v.z, r.s]);
+            scales.extend_from_slice(&[s.x, s.y, s.z]);
+        }
+
+        let mut ext = Value::Object(Default::default());
+        let attributes = &mut ext["attributes"];
+        attributes["TRANSLATION"] = self
+            .floats(&translations, Type::Vec3, None, false)
+            .value()
+            .into();
+        attributes["ROTATION"] = self
+            .floats(&rotations, Type::Vec4, None, false)
+            .value()
+            .into();
+        attributes["SCALE"] = self.floats(&scales, Type::Vec3, None, false).value().into();
+
+        ext
+    }
+
+    fn lights(&mut self, lights: &[Light]) {
+        use json::extensions::scene::khr_lights_punctual as khr;
+
+        if lights.is_empty() {
+            return;
+        }
+
+        let lights = lights
+            .iter()
+            .map(|light| {
+                let (type_, spot) = match light.kind {
+                    LightKind::Directional => (khr::Type::Directional, None),
+                    LightKind::Point => (khr::Type::Point, None),
+                    LightKind::Spot {
+                        inner_cone_angle,
+                        outer_cone_angle,
+                    } => (
+                        khr::Type::Spot,
+                        Some(khr::Spot {
+                            inner_cone_angle: inner_cone_angle.0,
+                            outer_cone_angle: outer_cone_angle.0,
+                        }),
+                    ),
+                };
+
+                khr::Light {
+                    color: light.color.into(),
+                    extensions: None,
+                    extras: Default::default(),
+                    intensity: light.intensity,
+                    name: None,
+                    range: light.range,
+                    spot,
+                    type_: Valid(type_),
+                }
+            })
+            .collect();
+
+        self.root.extensions = Some(json::extensions::Root {
+            khr_lights_punctual: Some(json::extensions::root::KhrLightsPunctual { lights }),
+        });
+        self.root
+            .extensions_used
+            .push(String::from("KHR_lights_punctual"));
+    }
+}
+
+fn node(node: &Node) -> json::Node {
+    let extensions = node.light.map(|light| json::extensions::scene::Node {
+        khr_lights_punctual: Some(
+            json::extensions::scene::khr_lights_punctual::KhrLightsPunctual {
+                light: Index::new(light as u32),
+            },
+        ),
+    });
+
+    json::Node {
+        camera: node.camera.map(|c| Index::new(c as u32)),
+        children: if node.children.is_empty() {
+            None
+        } else {
+            Some(
+                node.children
+                    .iter()
+                    .map(|&child| Index::new(child as u32))
+                    .collect(),
+            )
+        },
+        extensions,
+        extras: Default::default(),
+        matrix: None,
+        mesh: node.mesh.map(|m| Index::new(m as u32)),
+        name: node.name.clone(),
+        rotation: Some(json::scene::UnitQuaternion([
+            node.rotation.v.x,
+            node.rotation.v.y,
+            node.rotation.v.z,
+            node.rotation.s,
+        ])),
+        scale: Some(node.scale.into()),
+        translation: Some(node.translation.into()),
+        skin: node.skin.map(|s| Index::new(s as u32)),
+        weights: if node.weights.is_empty() {
+            None
+        } else {
+            Some(node.weights.clone())
+        },
+    }
+}
+
+fn material(mat: &Material) -> json::Material {
+    let info = |texture: Option<usize>, tex_coord, transform: TextureTransform| {
+        let extensions = if transform.is_identity() {
+            None
+        } else {
+            Some(json::extensions::texture::Info {
+                texture_transform: Some(json::extensions::texture::TextureTransform {
+                    offset: json::extensions::texture::TextureTransformOffset(
+                        transform.offset.into(),
+                    ),
+                    rotation: json::extensions::texture::TextureTransformRotation(
+                        transform.rotation,
+                    ),
+                    scale: json::extensions::texture::TextureTransformScale(transform.scale.into()),
+                    tex_coord: None,
+                    extras: Default::default(),
+                }),
+            })
+        };
+
+        texture.map(|index| json::texture::Info {
+            index: Index::new(index as u32),
+            tex_coord,
+            extensions,
+            extras: Default::default(),
+        })
+    };
+
+    let extensions = json::extensions::material::Material {
+        transmission: mat
+            .transmission
+            .map(|t| json::extensions::material::Transmission {
+                transmission_factor: json::extensions::material::TransmissionFactor(t.factor),
+                transmission_texture: info(t.tex, t.tex_coord, TextureTransform::default()),
+                extras: Default::default(),
+            }),
+        ior: if mat.ior != 1.5 {
+            Some(json::extensions::material::Ior {
+                ior: json::extensions::material::IndexOfRefraction(mat.ior),
+                extras: Default::default(),
+            })
+        } else {
+            None
+        },
+        unlit: if mat.unlit {
+            Some(json::extensions::material::Unlit {})
+        } else {
+            None
+        },
+    };
+    let extensions = if extensions.transmission.is_some()
+        || extensions.ior.is_some()
+        || extensions.unlit.is_some()
+    {
+        Some(extensions)
+    } else {
+        None
+    };
+
+    let alpha_mode = match mat.alpha_mode {
+        AlphaMode::Opaque => json::material::AlphaMode::Opaque,
+        AlphaMode::Mask => json::material::AlphaMode::Mask,
+        AlphaMode::Blend => json::material::AlphaMode::Blend,
+    };
+
+    json::Material {
+        double_sided: mat.double_sided,
+        alpha_mode: Valid(alpha_mode),
+        alpha_cutoff: if mat.alpha_mode == AlphaMode::Mask {
+            Some(json::material::AlphaCutoff(mat.alpha_cutoff))
+        } else {
+            None
+        },
+        pbr_metallic_roughness: json::material::PbrMetallicRoughness {
+            base_color_factor: json::material::PbrBaseColorFactor(mat.base_color.into()),
+            base_color_texture: info(mat.base_tex, mat.base_tex_coord, mat.base_tex_transform),
+            metallic_factor: json::material::StrengthFactor(mat.metallic),
+            roughness_factor: json::material::StrengthFactor(mat.roughness),
+            metallic_roughness_texture: info(
+                mat.metallic_tex,
+                mat.metallic_tex_coord,
+                mat.metallic_tex_transform,
+            ),
+            extensions: None,
+            extras: Default::default(),
+        },
+        normal_texture: mat.normal.map(|index| json::material::NormalTexture {
+            index: Index::new(index as u32),
+            scale: mat.normal_scale,
+            tex_coord: mat.normal_tex_coord,
+            extensions: None,
+            extras: Default::default(),
+        }),
+        occlusion_texture: mat
+            .occlusion_tex
+            .map(|index| json::material::OcclusionTexture {
+                index: Index::new(index as u32),
+                strength: json::material::StrengthFactor(mat.occlusion_str),
+                tex_coord: mat.occlusion_tex_coord,
+                extensions: None,
+                extras: Default::default(),
+            }),
+        emissive_texture: info(
+            mat.emissive_tex,
+            mat.emissive_tex_coord,
+            mat.emissive_tex_transform,
+        ),
+        emissive_factor: json::material::EmissiveFactor(mat.emissive_factor.into()),
+        extensions,
+        ..Default::default()
+    }
+}
+
+// emissive strength, clearcoat and sheen, as `Material::read_extensions` reads them
+fn material_extensions(mat: &Material) -> Vec<(&'static str, Value)> {
+    let mut extensions = Vec::new();
+
+    if mat.emissive_strength != 1.0 {
+        let mut ext = Value::Object(Default::default());
+        ext["emissiveStrength"] = Value::from(mat.emissive_strength);
+        extensions.push(("KHR_materials_emissive_strength", ext));
+    }
+
+    if let Some(c) = mat.clearcoat {
+        let mut ext = Value::Object(Default::default());
+        ext["clearcoatFactor"] = Value::from(c.factor);
+        ext["clearcoatRoughnessFactor"] = Value::from(c.roughness);
+        raw_info(&mut ext, "clearcoatTexture", c.tex, c.tex_coord);
+        raw_info(
+            &mut ext,
+            "clearcoatRoughnessTexture",
+            c.roughness_tex,
+            c.roughness_tex_coord,
+        );
+        raw_info(
+            &mut ext,
+            "clearcoatNormalTexture",
+            c.normal_tex,
+            c.normal_tex_coord,
+        );
+        extensions.push(("KHR_materials_clearcoat", ext));
+    }
+
+    if let Some(sheen) = mat.sheen {
+        let mut ext = Value::Object(Default::default());
+        let color: [f32; 3] = sheen.color.into();
+        ext["sheenColorFactor"] = Value::from(color.to_vec());
+        ext["sheenRoughnessFactor"] = Value::from(sheen.roughness);
+        raw_info(
+            &mut ext,
+            "sheenColorTexture",
+            sheen.color_tex,
+            sheen.color_tex_coord,
+        );
+        raw_info(
+            &mut ext,
+            "sheenRoughnessTexture",
+            sheen.roughness_tex,
+            sheen.roughness_tex_coord,
+        );
+        extensions.push(("KHR_materials_sheen", ext));
+    }
+
+    extensions
+}
+
+// (variant, material) pairs grouped by material
+fn variant_mappings(variants: &[(usize, usize)]) -> Value {
+    let mut materials: Vec<usize> = variants.iter().map(|&(_, material)| material).collect();
+    materials.sort_unstable();
+    materials.dedup();
+
+    let mut ext = Value::Object(Default::default());
+    ext["mappings"] = materials
+        .into_iter()
+        .map(|material| {
+            let mut mapping = Value::Object(Default::default());
+            mapping["material"] = Value::from(material);
+            mapping["variants"] = variants
+                .iter()
+                .filter(|&&(_, m)| m == material)
+                .map(|&(variant, _)| Value::from(variant))
+                .collect();
+            mapping
+        })
+        .collect();
+
+    ext
+}
+
+fn raw_info(ext: &mut Value, name: &str, texture: Option<usize>, tex_coord: u32) {
+    if let Some(index) = texture {
+        ext[name]["index"] = Value::from(index);
+        ext[name]["texCoord"] = Value::from(tex_coord);
+    }
+}
+
+// puts each extension in the `extensions` of its object and lists it in `extensionsUsed`
+fn with_extensions(mut document: Value, raw: Vec<(String, &'static str, Value)>) -> Value {
+    let mut used: Vec<&str> = Vec::new();
+
+    for (pointer, name, extension) in raw {
+        if let Some(object) = document.pointer_mut(&pointer) {
+            object["extensions"][name] = extension;
+        }
+        if !used.contains(&name) {
+            used.push(name);
+        }
+    }
+
+    if !used.is_empty() {
+        if !document["extensionsUsed"].is_array() {
+            document["extensionsUsed"] = Value::Array(Vec::new());
+        }
+        if let Some(list) = document["extensionsUsed"].as_array_mut() {
+            list.extend(used.into_iter().map(Value::from));
+        }
+    }
+
+    document
+}
+
+fn sampler(sampler: &Sampler) -> json::texture::Sampler {
+    json::texture::Sampler {
+        mag_filter: sampler.mag_filter.map(Valid),
+        min_filter: sampler.min_filter.map(Valid),
+        wrap_s: Valid(sampler.wrap_s),
+        wrap_t: Valid(sampler.wrap_t),
+        name: None,
+        extensions: None,
+        extras: Default::default(),
+    }
+}
+
+// normal and occlusion textures can't carry KHR_texture_transform in gltf 0.16
+fn has_texture_transform(mat: &Material) -> bool {
+    (mat.base_tex.is_some() && !mat.base_tex_transform.is_identity())
+        || (mat.metallic_tex.is_some() && !mat.metallic_tex_transform.is_identity())
+        || (mat.emissive_tex.is_some() && !mat.emissive_tex_transform.is_identity())
+}
+
+fn camera(cam: &Camera) -> json::Camera {
+    let yfov = Rad::from(Deg(cam.fov)).0;
+    let (type_, perspective, orthographic) = match cam.projection {
+        Projection::Perspective {
+            aspect_ratio,
+            znear,
+            zfar,
+        } => (
+            json::camera::Type::Perspective,
+            Some(json::camera::Perspective {
+                aspect_ratio,
+                yfov,
+                zfar,
+                znear,
+                extensions: None,
+                extras: Default::default(),
+            }),
+            None,
+        ),
+        Projection::Orthographic {
+            xmag,
+            ymag,
+            znear,
+            zfar,
+        } => (
+            json::camera::Type::Orthographic,
+            None,
+            Some(json::camera::Orthographic {
+                xmag,
+                ymag,
+                zfar,
+                znear,
+                extensions: None,
+                extras: Default::default(),
+            }),
+        ),
+    };
+
+    json::Camera {
+        name: None,
+        orthographic,
+        perspective,
+        type_: Valid(type_),
+        extensions: None,
+        extras: Default::default(),
+    }
+}
+
+fn mode(mode: gl::types::GLenum) -> json::mesh::Mode {
+    use json::mesh::Mode;
+
+    match mode {
+        gl::POINTS => Mode::Points,
+        gl::LINES => Mode::Lines,
+        gl::LINE_LOOP => Mode::LineLoop,
+        gl::LINE_STRIP => Mode::LineStrip,
+        gl::TRIANGLE_STRIP => Mode::TriangleStrip,
+        gl::TRIANGLE_FAN => Mode::TriangleFan,
+        _ => Mode::Triangles,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::test_util::test_asset;
+    use cgmath::prelude::*;
+    use gltf::texture::WrappingMode;
+
+    fn round_trip(name: &str, glb: bool) -> (SceneData, SceneData) {
+        let data = SceneData::load(test_asset(name)).unwrap();
+
+        let out = std::env::temp_dir().join(format!(
+            "glboot_export_{}_{}.{}",
+            std::process::id(),
+            name.replace('.', "_"),
+            if glb { "glb" } else { "gltf" }
+        ));
+
+        if glb {
+            data.save_glb(&out).unwrap();
+        } else {
+            data.save_gltf(&out).unwrap();
+        }
+
+        let back = SceneData::load(&out).unwrap();
+        let _ = fs::remove_file(&out);
+        let _ = fs::remove_file(out.with_extension("bin"));
+
+        (data, back)
+    }
+
+    #[test]
+    fn gltf_round_trip() {
+        let (data, back) = round_trip("triangle.gltf", false);
+
+        assert_eq!(back.nodes.len(), data.nodes.len());
+        assert_eq!(back.roots(), data.roots());
+        assert_eq!(back.nodes[0].children, data.nodes[0].children);
+        assert_eq!(back.nodes[1].translation, data.nodes[1].translation);
+        assert_eq!(back.nodes[1].mesh, Some(0));
+        assert_eq!(back.materials[0].base_color, data.materials[0].base_color);
+
+        let (prim, prim_back) = (&data.meshes[0].primitives[0], &back.meshes[0].primitives[0]);
+        assert_eq!(prim_back.indices, prim.indices);
+        assert_eq!(prim_back.vertices.len(), prim.vertices.len());
+        for (a, b) in prim.vertices.iter().zip(prim_back.vertices.iter()) {
+            assert_eq!(a.pos, b.pos);
+            assert_eq!(a.normal, b.normal);
+        }
+    }
+
+    #[test]
+    fn glb_round_trip() {
+        let (data, back) = round_trip("morph.gltf", true);
+
+        assert_eq!(back.meshes[0].weights, data.meshes[0].weights);
+        assert_eq!(
+            back.meshes[0].primitives[0].targets[0].positions,
+            data.meshes[0].primitives[0].targets[0].positions
+        );
+        assert_eq!(back.animations.len(), data.animations.len());
+
+        let (anim, anim_back) = (data.animations[0].clone(), back.animations[0].clone());
+        let (mut nodes, mut nodes_back) = (data.nodes.clone(), back.nodes.clone());
+        anim.animate(0.5, &mut nodes);
+        anim_back.animate(0.5, &mut nodes_back);
+        assert_eq!(nodes[0].weights, nodes_back[0].weights);
+    }
+
+    #[test]
+    fn textures() {
+        let (quad, quad_back) = round_trip("quad.obj", true);
+        assert_eq!(quad_back.images.len(), 1);
+        assert_eq!(quad_back.images[0].pixels, quad.images[0].pixels);
+        assert_eq!(quad_back.materials[0].base_tex, Some(0));
+
+        // textures sharing an image keep their own samplers
+        let (shared, shared_back) = round_trip("shared_textures.gltf", false);
+        assert_eq!(shared_back.images.len(), 1);
+        assert_eq!(shared_back.textures, shared.textures);
+        assert_eq!(
+            shared_back.textures[0].sampler.wrap_s,
+            WrappingMode::ClampToEdge
+        );
+        assert_eq!(shared_back.materials[0].base_tex, Some(1));
+        assert_eq!(shared_back.materials[0].normal_scale, 0.5);
+    }
+
+    #[test]
+    fn cameras() {
+        let (cams, cams_back) = round_trip("cameras.gltf", false);
+        assert_eq!(cams_back.nodes[0].camera, Some(0));
+        assert_eq!(cams_back.cameras[1], cams.cameras[1]);
+        assert_eq!(cams_back.cameras[0].projection, cams.cameras[0].projection);
+        assert!((cams_back.cameras[0].fov - cams.cameras[0].fov).abs() < 1e-4);
+    }
+
+    #[test]
+    fn lights() {
+        let (lights, lights_back) = round_trip("lights.gltf", false);
+        assert_eq!(lights_back.lights, lights.lights);
+        assert_eq!(lights_back.nodes[2].light, Some(2));
+    }
+
+    #[test]
+    fn texture_transforms() {
+        let (quantized, quantized_back) = round_trip("quantized.gltf", false);
+        assert_eq!(
+            quantized_back.materials[0].base_tex_transform,
+            quantized.materials[0].base_tex_transform
+        );
+    }
+
+    #[test]
+    fn alpha_modes() {
+        let (alpha, alpha_back) = round_trip("alpha_modes.gltf", false);
+        for (mat, back) in alpha.materials.iter().zip(alpha_back.materials.iter()) {
+            assert_eq!(
+                (back.alpha_mode, back.alpha_cutoff),
+                (mat.alpha_mode, mat.alpha_cutoff)
+            );
+        }
+    }
+
+    #[test]
+    fn pbr_extensions() {
+        let (pbr, pbr_back) = round_trip("pbr_extensions.gltf", false);
+        let mat = pbr_back.materials[0];
+        assert_eq!(mat.transmission.map(|t| t.factor), Some(0.75));
+        assert_eq!(mat.ior, 1.4);
+        assert!(mat.unlit);
+        assert_eq!(mat.emissive_strength, 4.0);
+        assert_eq!(mat.clearcoat, pbr.materials[0].clearcoat);
+        assert_eq!(mat.sheen, pbr.materials[0].sheen);
+        assert!(pbr_back.materials[1].clearcoat.is_none());
+    }
+
+    #[test]
+    fn variants() {
+        let (variants, variants_back) = round_trip("variants.gltf", true);
+        assert_eq!(variants_back.variants, variants.variants);
+        for (mesh, back) in variants.meshes.iter().zip(variants_back.meshes.iter()) {
+            for (prim, prim_back) in mesh.primitives.iter().zip(back.primitives.iter()) {
+                assert_eq!(prim_back.variants, prim.variants);
+            }
+        }
+        assert!(!variants_back.meshes[0].primitives[0].variants.is_empty());
+    }
+
+    #[test]
+    fn instancing() {
+        let (instanced, instanced_back) = round_trip("instancing.gltf", true);
+        let instances = &instanced.nodes[0].instances;
+        assert_eq!(instanced_back.nodes[0].instances.len(), instances.len());
+        for (a, b) in instances
+            .iter()
+            .zip(instanced_back.nodes[0].instances.iter())
+        {
+            let error: f32 = (0..4).map(|c| (a[c] - b[c]).magnitude()).sum();
+            assert!(error < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use cgmath::{prelude::*, Vector3, Vector4};
+
+use super::{Attributes, MorphTarget, PrimitiveData, Vertice};
+
+// Geometry processing for primitives that arrive without some of the attributes the
+// shaders expect.
+impl PrimitiveData {
+    /// Vertex indices of every triangle, strips and fans are unrolled.
+    /// Primitives that aren't made of triangles have none.
+    pub fn triangles(&self) -> Vec<[usize; 3]> {
+        let indices: Vec<usize> = if self.indices.is_empty() {
+            (0..self.vertices.len()).collect()
+        } else {
+            self.indices.iter().map(|&i| i as usize).collect()
+        };
+
+        match self.mode {
+            gl::TRIANGLES => indices
+                .chunks_exact(3)
+                .map(|t| [t[0], t[1], t[2]])
+                .collect(),
+            // every other triangle of a strip has its winding flipped
+            gl::TRIANGLE_STRIP => indices
+                .windows(3)
+                .enumerate()
+                .map(|(i, t)| {
+                    if i % 2 == 0 {
+                        [t[0], t[1], t[2]]
+                    } else {
+                        [t[1], t[0], t[2]]
+                    }
+                })
+                .collect(),
+            gl::TRIANGLE_FAN => indices
+                .windows(2)
+                .skip(1)
+                .map(|t| [indices[0], t[0], t[1]])
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Gives each triangle its own vertices facing the same way, as glTF asks for
+    /// primitives without normals.
+    ///
+    /// Shared vertices are split, leaving an unindexed triangle list, morph targets included.
+    pub fn generate_flat_normals(&mut self) {
+        let triangles = self.triangles();
+        if triangles.is_empty() {
+            return;
+        }
+
+        let mut uses = vec![0_u32; self.vertices.len()];
+        triangles.iter().flatten().for_each(|&i| uses[i] += 1);
+
+        // nothing to split, the normals can be written in place
+        if self.mode == gl::TRIANGLES && uses.iter().all(|&count| count <= 1) {
+            for tri in triangles.iter() {
+                let normal = face_normal(&self.vertices, tri);
+                for &i in tri.iter() {
+                    self.vertices[i].normal = normal;
+                }
+            }
+
+            self.attributes.insert(Attributes::NORMAL);
+            return;
+        }
+
+        let mut vertices = Vec::with_capacity(triangles.len() * 3);
+        for tri in triangles.iter() {
+            let [a, b, c] = [
+                self.vertices[tri[0]],
+                self.vertices[tri[1]],
+                self.vertices[tri[2]],
+            ];
+            let normal = face_normal(&self.vertices, tri);
+
+            vertices.extend([a, b, c].iter().map(|&v| Vertice { normal, ..v }));
+        }
+
+        let unweld = |data: &[Vector3<f32>]| -> Vec<Vector3<f32>> {
+            if data.is_empty() {
+                Vec::new()
+            } else {
+                triangles.iter().flatten().map(|&i| data[i]).collect()
+            }
+        };
+
+        self.targets = self
+            .targets
+            .iter()
+            .map(|target| MorphTarget {
+                positions: unweld(&target.positions),
+                normals: unweld(&target.normals),
+                tangents: unweld(&target.tangents),
+            })
+            .collect();
+
+        self.vertices = vertices;
+        self.indices = Vec::new();
+        self.mode = gl::TRIANGLES;
+        self.attributes.insert(Attributes::NORMAL);
+    }
+
+    /// Generates MikkTSpace tangents from the normals and the UV set the normal map reads, the
+    /// handedness goes in `w`.
+    ///
+    /// MikkTSpace gives each triangle corner its own tangent, vertices whose corners disagree,
+    /// e.g. on mirrored UVs, are split. The primitive becomes an indexed triangle list then.
+    pub fn generate_tangents(&mut self, tex_coord: u32) {
+        let triangles = self.triangles();
+        if triangles.is_empty() {
+            return;
+        }
+
+        let mut corners = Corners {
+            vertices: &self.vertices,
+            triangles: &triangles,
+            tex_coord,
+            tangents: vec![Vector4::new(1.0, 0.0, 0.0, 1.0); triangles.len() * 3],
+        };
+        bevy_mikktspace::generate_tangents(&mut corners);
+        let tangents = corners.tangents;
+
+        // (vertex, tangent, copy) for the corners that didn't match the first one
+        let mut copies: Vec<(usize, Vector4<f32>, usize)> = Vec::new();
+        let mut first = vec![None; self.vertices.len()];
+        let mut indices = Vec::with_capacity(tangents.len());
+
+        for (corner, &tangent) in tangents.iter().enumerate() {
+            let vertex = triangles[corner / 3][corner % 3];
+
+            let index = match first[vertex] {
+                None => {
+                    first[vertex] = Some(tangent);
+                    vertex
+                }
+                Some(t) if t == tangent => vertex,
+                Some(_) => match copies.iter().find(|c| c.0 == vertex && c.1 == tangent) {
+                    Some(&(_, _, copy)) => copy,
+                    None => {
+                        let copy = self.split(vertex);
+                        copies.push((vertex, tangent, copy));
+                        copy
+                    }
+                },
+            };
+
+            self.vertices[index].tangent = tangent;
+            indices.push(index as u32);
+        }
+
+        if !copies.is_empty() {
+            self.indices = indices;
+            self.mode = gl::TRIANGLES;
+        }
+        self.attributes.insert(Attributes::TANGENT);
+    }
+
+    // copies a vertex with its morph target data, returns the index of the copy
+    fn split(&mut self, vertex: usize) -> usize {
+        self.vertices.push(self.vertices[vertex]);
+
+        for target in self.targets.iter_mut() {
+            for list in [
+                &mut target.positions,
+                &mut target.normals,
+                &mut target.tangents,
+            ]
+            .iter_mut()
+            {
+                if !list.is_empty() {
+                    list.push(list[vertex]);
+                }
+            }
+        }
+
+        self.vertices.len() - 1
+    }
+}
+
+// the triangle corners as MikkTSpace reads them, with the tangent it gives each one
+struct Corners<'a> {
+    vertices: &'a [Vertice],
+    triangles: &'a [[usize; 3]],
+    tex_coord: u32,
+    tangents: Vec<Vector4<f32>>,
+}
+
+impl Corners<'_> {
+    fn vertex(&self, face: usize, vert: usize) -> &Vertice {
+        &self.vertices[self.triangles[face][vert]]
+    }
+}
+
+impl bevy_mikktspace::Geometry for Corners<'_> {
+    fn num_faces(&self) -> usize {
+        self.triangles.len()
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).pos.into()
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).normal.into()
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        let v = self.vertex(face, vert);
+        if self.tex_coord == 1 {
+            v.tex1.into()
+        } else {
+            v.tex.into()
+        }
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.tangents[face * 3 + vert] = tangent.into();
+    }
+}
+
+fn face_normal(vertices: &[Vertice], tri: &[usize; 3]) -> Vector3<f32> {
+    let [a, b, c] = [
+        vertices[tri[0]].pos,
+        vertices[tri[1]].pos,
+        vertices[tri[2]].pos,
+    ];
+    let normal = (b - a).cross(c - a);
+
+    // degenerate triangles are left without a normal
+    if normal.magnitude2() > 0.0 {
+        normal.normalize()
+    } else {
+        normal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aabb::Aabb;
+    use cgmath::{vec2, vec3, vec4, Vector2};
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    // a unit quad on the XY plane made of two triangles sharing an edge
+    fn quad(flip_v: bool) -> PrimitiveData {
+        let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let vertices = corners
+            .iter()
+            .map(|&(x, y)| Vertice {
+                pos: vec3(x, y, 0.0),
+                tex: vec2(x, if flip_v { 1.0 - y } else { y }),
+                ..Vertice::default()
+            })
+            .collect();
+
+        PrimitiveData {
+            vertices,
+            indices: vec![0, 1, 2, 0, 2, 3],
+            material: None,
+            aabb: Aabb::default(),
+            mode: gl::TRIANGLES,
+            targets: Vec::new(),
+            attributes: Attributes::TEX_COORD_0,
+            variants: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn flat_normals() {
+        let mut prim = quad(false);
+        prim.targets.push(MorphTarget {
+            positions: vec![vec3(0.0, 0.0, 1.0); 4],
+            ..MorphTarget::default()
+        });
+        prim.generate_flat_normals();
+
+        assert_eq!(prim.vertices.len(), 6);
+        assert!(prim.indices.is_empty());
+        assert_eq!(prim.vertices[3].pos, vec3(0.0, 0.0, 0.0));
+        assert_eq!(prim.targets[0].positions.len(), 6);
+        assert!(prim.targets[0].normals.is_empty());
+
+        for v in prim.vertices.iter() {
+            assert_eq!(v.normal, vec3(0.0, 0.0, 1.0));
+        }
+        assert!(prim.attributes.contains(Attributes::NORMAL));
+
+        // unshared vertices keep their layout
+        let mut prim = quad(false);
+        prim.indices = vec![0, 1, 2];
+        prim.vertices.truncate(3);
+        prim.generate_flat_normals();
+
+        assert_eq!(prim.indices, vec![0, 1, 2]);
+        assert_eq!(prim.vertices[1].normal, vec3(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn strip_and_fan_triangles() {
+        let mut prim = quad(false);
+        prim.indices = vec![0, 1, 3, 2];
+        prim.mode = gl::TRIANGLE_STRIP;
+        assert_eq!(prim.triangles(), vec![[0, 1, 3], [3, 1, 2]]);
+
+        prim.indices.clear();
+        prim.mode = gl::TRIANGLE_FAN;
+        assert_eq!(prim.triangles(), vec![[0, 1, 2], [0, 2, 3]]);
+
+        prim.mode = gl::LINES;
+        assert!(prim.triangles().is_empty());
+    }
+
+    #[test]
+    fn tangents() {
+        for &flip_v in [false, true].iter() {
+            let mut prim = quad(flip_v);
+            prim.generate_flat_normals();
+            prim.generate_tangents(0);
+
+            let w = if flip_v { -1.0 } else { 1.0 };
+            for v in prim.vertices.iter() {
+                assert!((v.tangent - vec4(1.0, 0.0, 0.0, w)).magnitude() < 1e-5);
+            }
+        }
+
+        // a normal map on the second set follows that set, here rotated a quarter turn
+        let mut prim = quad(false);
+        for v in prim.vertices.iter_mut() {
+            v.tex1 = vec2(v.pos.y, 1.0 - v.pos.x);
+        }
+        prim.generate_flat_normals();
+        prim.generate_tangents(1);
+
+        for v in prim.vertices.iter() {
+            assert!((v.tangent - vec4(0.0, 1.0, 0.0, 1.0)).magnitude() < 1e-5);
+        }
+    }
+
+    fn fan(vertices: &[(Vector3<f32>, Vector2<f32>)]) -> PrimitiveData {
+        PrimitiveData {
+            vertices: vertices
+                .iter()
+                .map(|&(pos, tex)| Vertice {
+                    pos,
+                    tex,
+                    normal: vec3(0.0, 0.0, 1.0),
+                    ..Vertice::default()
+                })
+                .collect(),
+            indices: vec![0, 1, 2, 0, 2, 3],
+            attributes: Attributes::NORMAL | Attributes::TEX_COORD_0,
+            ..quad(false)
+        }
+    }
+
+    // expected values worked out by hand following the MikkTSpace reference
+    #[test]
+    fn mikktspace_reference() {
+        // the second triangle bends out of the plane of the normals, MikkTSpace measures the
+        // corner angles in that plane: 90 and 135 degrees around vertex 0, 45 and 26.57 around 2
+        let mut prim = fan(&[
+            (vec3(0.0, 0.0, 0.0), vec2(0.0, 0.0)),
+            (vec3(1.0, 0.0, 0.0), vec2(1.0, 0.0)),
+            (vec3(0.0, 1.0, 0.0), vec2(0.0, 1.0)),
+            (vec3(-1.0, -1.0, 1.0), vec2(-1.0, 0.0)),
+        ]);
+        prim.generate_tangents(0);
+
+        let expected = [
+            vec4(0.889_130, 0.457_652, 0.0, 1.0),
+            vec4(1.0, 0.0, 0.0, 1.0),
+            vec4(0.959_266, 0.282_503, 0.0, 1.0),
+            vec4(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0.0, 1.0),
+        ];
+        assert_eq!(prim.vertices.len(), 4);
+        for (v, expected) in prim.vertices.iter().zip(expected.iter()) {
+            assert!((v.tangent - expected).magnitude() < 1e-4);
+        }
+
+        // mirrored UVs, the corners of the shared edge get their own vertices
+        let mut prim = fan(&[
+            (vec3(0.0, 0.0, 0.0), vec2(0.0, 0.0)),
+            (vec3(1.0, 0.0, 0.0), vec2(1.0, 0.0)),
+            (vec3(0.0, 1.0, 0.0), vec2(0.0, 1.0)),
+            (vec3(-1.0, 0.0, 0.0), vec2(1.0, 0.0)),
+        ]);
+        prim.targets.push(MorphTarget {
+            positions: vec![vec3(0.0, 0.0, 1.0); 4],
+            ..MorphTarget::default()
+        });
+        prim.generate_tangents(0);
+
+        assert_eq!(prim.indices, vec![0, 1, 2, 4, 5, 3]);
+        assert_eq!(prim.targets[0].positions.len(), 6);
+        assert_eq!(prim.vertices[4].pos, prim.vertices[0].pos);
+        for (i, v) in prim.vertices.iter().enumerate() {
+            let expected = if i < 3 {
+                vec4(1.0, 0.0, 0.0, 1.0)
+            } else {
+                vec4(-1.0, 0.0, 0.0, -1.0)
+            };
+            assert!((v.tangent - expected).magnitude() < 1e-5);
+        }
+    }
+}
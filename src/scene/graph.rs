@@ -0,0 +1,383 @@
+use cgmath::{prelude::*, Matrix4};
+use thiserror::Error;
+
+use super::{animations::Animation, data::SceneRoots, node::Descendants, skin::Skin, Node};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum GraphError {
+    #[error("there's no node {0}")]
+    MissingNode(usize),
+    #[error("node {node} can't be moved below its descendant {parent}")]
+    Cycle { node: usize, parent: usize },
+}
+
+/// Structural edits of the node hierarchy.
+///
+/// Nodes are referenced by index from children lists, scene roots, skin joints and animation
+/// channels, every edit keeps those in sync. Used by `Scene`, which also has to rebuild its
+/// traversal order afterwards.
+pub(super) struct Graph<'a> {
+    pub nodes: &'a mut Vec<Node>,
+    pub scenes: &'a mut [SceneRoots],
+    pub skins: &'a mut Vec<Skin>,
+    pub animations: &'a mut [Animation],
+}
+
+impl Graph<'_> {
+    pub fn parent(&self, node: usize) -> Option<usize> {
+        self.nodes.iter().position(|n| n.children.contains(&node))
+    }
+
+    /// Transform of `node` relative to the roots.
+    pub fn world_transform(&self, node: usize) -> Matrix4<f32> {
+        let mut transform = self.nodes[node].transform;
+        let mut current = node;
+        while let Some(parent) = self.parent(current) {
+            transform = self.nodes[parent].transform * transform;
+            current = parent;
+        }
+
+        transform
+    }
+
+    /// Adds `node` under `parent`, or as a root of `scene`.
+    pub fn spawn(
+        &mut self,
+        node: Node,
+        parent: Option<usize>,
+        scene: usize,
+    ) -> Result<usize, GraphError> {
+        self.check(parent)?;
+
+        let index = self.nodes.len();
+        self.nodes.push(node);
+        self.attach(index, parent, scene);
+
+        Ok(index)
+    }
+
+    /// Removes `node` along with everything below it.
+    ///
+    /// Returns the new index of every old node, `None` for the removed ones. Animation channels
+    /// of removed nodes are dropped, as are skins that lose a joint, the nodes using them are left
+    /// unskinned.
+    pub fn remove(&mut self, node: usize) -> Result<Vec<Option<usize>>, GraphError> {
+        self.check(Some(node))?;
+
+        let mut removed = vec![false; self.nodes.len()];
+        removed[node] = true;
+        for child in Descendants::new(self.nodes, node) {
+            removed[child] = true;
+        }
+
+        let mut next = 0;
+        let map: Vec<Option<usize>> = removed
+            .iter()
+            .map(|&removed| {
+                (!removed).then(|| {
+                    next += 1;
+                    next - 1
+                })
+            })
+            .collect();
+
+        let mut index = 0;
+        self.nodes.retain(|_| {
+            index += 1;
+            !removed[index - 1]
+        });
+
+        for n in self.nodes.iter_mut() {
+            n.children = n.children.iter().filter_map(|&c| map[c]).collect();
+        }
+        for scene in self.scenes.iter_mut() {
+            scene.roots = scene.roots.iter().filter_map(|&r| map[r]).collect();
+        }
+
+        // a skin missing a joint can't deform its mesh anymore
+        let mut next = 0;
+        let skin_map: Vec<Option<usize>> = self
+            .skins
+            .iter()
+            .map(|skin| {
+                skin.joints.iter().all(|j| map[j.node].is_some()).then(|| {
+                    next += 1;
+                    next - 1
+                })
+            })
+            .collect();
+
+        let mut index = 0;
+        self.skins.retain(|_| {
+            index += 1;
+            skin_map[index - 1].is_some()
+        });
+        for joint in self.skins.iter_mut().flat_map(|s| s.joints.iter_mut()) {
+            joint.node = map[joint.node].unwrap();
+        }
+        for n in self.nodes.iter_mut() {
+            n.skin = n.skin.and_then(|s| skin_map[s]);
+        }
+
+        for anim in self.animations.iter_mut() {
+            anim.retarget(&map);
+        }
+
+        Ok(map)
+    }
+
+    /// Moves `node` under `parent`, or to the roots of `scene`.
+    ///
+    /// With `keep_world` the local transform is changed so the node stays where it was,
+    /// otherwise it keeps its local transform. Nothing changes when the move would create a
+    /// cycle.
+    pub fn reparent(
+        &mut self,
+        node: usize,
+        parent: Option<usize>,
+        scene: usize,
+        keep_world: bool,
+    ) -> Result<(), GraphError> {
+        self.check(Some(node).into_iter().chain(parent))?;
+        if let Some(parent) = parent {
+            if parent == node || Descendants::new(self.nodes, node).any(|n| n == parent) {
+                return Err(GraphError::Cycle { node, parent });
+            }
+        }
+
+        if keep_world {
+            let world = self.world_transform(node);
+            let parent_world = parent.map_or(Matrix4::identity(), |p| self.world_transform(p));
+            let local = parent_world
+                .invert()
+                .map_or(world, |inverse| inverse * world);
+
+            self.nodes[node].set_transform(local);
+        }
+
+        self.detach(node);
+        self.attach(node, parent, scene);
+        Ok(())
+    }
+
+    /// Copies `node` and everything below it, placing the copy under `parent` or as a root of
+    /// `scene`.
+    ///
+    /// The copies are appended to the nodes, the index of the new root is returned. Skins whose
+    /// joints are all part of the copy are duplicated, the others are shared with the original.
+    /// Animations still only target the original nodes.
+    pub fn clone_subtree(
+        &mut self,
+        node: usize,
+        parent: Option<usize>,
+        scene: usize,
+    ) -> Result<usize, GraphError> {
+        self.check(Some(node).into_iter().chain(parent))?;
+
+        let subtree: Vec<usize> = std::iter::once(node)
+            .chain(Descendants::new(self.nodes, node))
+            .collect();
+
+        let base = self.nodes.len();
+        let mut map = vec![None; base];
+        for (i, &old) in subtree.iter().enumerate() {
+            map[old] = Some(base + i);
+        }
+
+        let mut skin_map = vec![None; self.skins.len()];
+        for &old in subtree.iter() {
+            let skin = match self.nodes[old].skin {
+                Some(skin) if skin_map[skin].is_none() => skin,
+                _ => continue,
+            };
+
+            if self.skins[skin]
+                .joints
+                .iter()
+                .all(|j| map[j.node].is_some())
+            {
+                let mut copy = self.skins[skin].clone();
+                for joint in copy.joints.iter_mut() {
+                    joint.node = map[joint.node].unwrap();
+                }
+
+                skin_map[skin] = Some(self.skins.len());
+                self.skins.push(copy);
+            }
+        }
+
+        for &old in subtree.iter() {
+            let mut copy = self.nodes[old].clone();
+            copy.children = copy.children.iter().filter_map(|&c| map[c]).collect();
+            copy.skin = copy.skin.map(|s| skin_map[s].unwrap_or(s));
+
+            self.nodes.push(copy);
+        }
+
+        self.attach(base, parent, scene);
+        Ok(base)
+    }
+
+    fn check(&self, nodes: impl IntoIterator<Item = usize>) -> Result<(), GraphError> {
+        match nodes.into_iter().find(|&node| node >= self.nodes.len()) {
+            Some(node) => Err(GraphError::MissingNode(node)),
+            None => Ok(()),
+        }
+    }
+
+    fn attach(&mut self, node: usize, parent: Option<usize>, scene: usize) {
+        match parent {
+            Some(parent) => self.nodes[parent].children.push(node),
+            None => self.scenes[scene].roots.push(node),
+        }
+    }
+
+    fn detach(&mut self, node: usize) {
+        for n in self.nodes.iter_mut() {
+            n.children.retain(|&c| c != node);
+        }
+        for scene in self.scenes.iter_mut() {
+            scene.roots.retain(|&r| r != node);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{test_util::test_asset, SceneData};
+    use cgmath::{vec3, Deg, Quaternion};
+
+    // Root -> (A -> B, C), B is the joint of a skin used by C
+    fn scene() -> SceneData {
+        let path = test_asset("skin.gltf");
+        let mut data = SceneData::load(path).unwrap();
+
+        let mut nodes: Vec<Node> = (0..4).map(|_| Node::with_mesh(0)).collect();
+        nodes[0].children = vec![1, 3];
+        nodes[1].children = vec![2];
+        nodes[1].translation = vec3(1.0, 0.0, 0.0);
+        nodes[1].update();
+        nodes[3].skin = Some(0);
+
+        data.nodes = nodes;
+        data.scenes = vec![SceneRoots::new(None, vec![0])];
+        data.skins[0].joints[0].node = 2;
+        data.animations[0].retarget(&[None, Some(2)]);
+        data
+    }
+
+    fn graph(data: &mut SceneData) -> Graph<'_> {
+        Graph {
+            nodes: &mut data.nodes,
+            scenes: &mut data.scenes,
+            skins: &mut data.skins,
+            animations: &mut data.animations,
+        }
+    }
+
+    #[test]
+    fn remove_subtree() {
+        let mut data = scene();
+        let map = graph(&mut data).remove(1).unwrap();
+
+        assert_eq!(map, vec![Some(0), None, None, Some(1)]);
+        assert_eq!(data.nodes.len(), 2);
+        assert_eq!(data.nodes[0].children, vec![1]);
+
+        // the skin lost its joint
+        assert!(data.skins.is_empty());
+        assert_eq!(data.nodes[1].skin, None);
+        assert_eq!(data.animations[0].raw_channels().count(), 0);
+    }
+
+    #[test]
+    fn reparent_keeping_world() {
+        let mut data = scene();
+        let mut graph = graph(&mut data);
+        graph.nodes[3].rotation = Quaternion::from_angle_y(Deg(90.0));
+        graph.nodes[3].translation = vec3(0.0, 2.0, 0.0);
+        graph.nodes[3].update();
+        let world = graph.world_transform(3);
+
+        graph.reparent(3, Some(2), 0, true).unwrap();
+        assert_eq!(graph.parent(3), Some(2));
+        assert_eq!(graph.nodes[0].children, vec![1]);
+
+        let moved = graph.world_transform(3);
+        for i in 0..4 {
+            assert!((moved[i] - world[i]).magnitude() < 1e-5);
+        }
+        assert!((graph.nodes[3].translation - vec3(-1.0, 2.0, 0.0)).magnitude() < 1e-5);
+
+        // without it the local transform stays
+        graph.reparent(3, None, 0, false).unwrap();
+        assert_eq!(graph.parent(3), None);
+        assert_eq!(graph.scenes[0].roots, vec![0, 3]);
+        assert!((graph.nodes[3].translation - vec3(-1.0, 2.0, 0.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn reparent_below_itself() {
+        let mut data = scene();
+        let mut graph = graph(&mut data);
+
+        assert_eq!(
+            graph.reparent(1, Some(2), 0, false),
+            Err(GraphError::Cycle { node: 1, parent: 2 })
+        );
+        assert_eq!(
+            graph.reparent(1, Some(9), 0, false),
+            Err(GraphError::MissingNode(9))
+        );
+        // left as it was
+        assert_eq!(graph.parent(1), Some(0));
+        assert_eq!(graph.nodes[1].children, vec![2]);
+    }
+
+    #[test]
+    fn clone_subtree() {
+        let mut data = scene();
+        let mut graph = graph(&mut data);
+
+        let copy = graph.clone_subtree(0, None, 0).unwrap();
+        assert_eq!(copy, 4);
+        assert_eq!(graph.nodes.len(), 8);
+        assert_eq!(graph.nodes[4].children, vec![5, 7]);
+        assert_eq!(graph.nodes[5].children, vec![6]);
+        assert_eq!(graph.scenes[0].roots, vec![0, 4]);
+
+        // the joint was copied too, so is the skin
+        assert_eq!(graph.skins.len(), 2);
+        assert_eq!(graph.skins[1].joints[0].node, 6);
+        assert_eq!(graph.nodes[7].skin, Some(1));
+
+        // the joint stays outside of this copy, the skin is shared
+        let copy = graph.clone_subtree(3, Some(1), 0).unwrap();
+        assert_eq!(graph.nodes[copy].skin, Some(0));
+        assert_eq!(graph.parent(copy), Some(1));
+    }
+
+    #[test]
+    fn missing_nodes() {
+        let mut data = scene();
+        let mut graph = graph(&mut data);
+
+        assert_eq!(
+            graph.spawn(Node::with_mesh(0), Some(4), 0),
+            Err(GraphError::MissingNode(4))
+        );
+        assert_eq!(graph.remove(4), Err(GraphError::MissingNode(4)));
+        assert_eq!(
+            graph.clone_subtree(4, None, 0),
+            Err(GraphError::MissingNode(4))
+        );
+        assert_eq!(
+            graph.clone_subtree(1, Some(7), 0),
+            Err(GraphError::MissingNode(7))
+        );
+        // nothing was added or removed
+        assert_eq!(graph.nodes.len(), 4);
+        assert_eq!(graph.nodes[0].children, vec![1, 3]);
+    }
+}
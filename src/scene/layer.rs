@@ -0,0 +1,97 @@
+use super::{animations::Cursor, node::Descendants, skin::Skin, Animation, Node, Playback, Pose};
+
+/// Nodes an animation layer is allowed to drive.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mask {
+    All,
+    Nodes(Vec<usize>),
+}
+
+impl Mask {
+    /// The joints of a skin.
+    pub fn joints(skin: &Skin) -> Self {
+        Mask::Nodes(skin.joints.iter().map(|joint| joint.node).collect())
+    }
+
+    /// `root` and every node below it, e.g. the upper body from the spine down.
+    pub fn subtree(nodes: &[Node], root: usize) -> Self {
+        let mut subtree: Vec<usize> = Descendants::new(nodes, root).collect();
+        subtree.push(root);
+
+        Mask::Nodes(subtree)
+    }
+
+    pub fn contains(&self, node: usize) -> bool {
+        match self {
+            Mask::All => true,
+            Mask::Nodes(nodes) => nodes.contains(&node),
+        }
+    }
+
+    /// Follows the nodes to their new indices after a removal, see `Graph::remove`.
+    pub(super) fn retarget(&mut self, map: &[Option<usize>]) {
+        if let Mask::Nodes(nodes) = self {
+            *nodes = nodes.iter().filter_map(|&node| map[node]).collect();
+        }
+    }
+}
+
+/// How a layer is combined with the pose below it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Blend {
+    Override,
+    Additive(Pose), // adds the difference between the clip and this reference pose
+}
+
+/// A clip played on top of the selected clips, see `Animations::layers`.
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub clip: usize,
+    pub playback: Playback,
+    pub blend: Blend,
+    pub weight: f32,
+    pub mask: Mask,
+    cursor: Cursor,
+}
+
+impl Layer {
+    /// A layer overriding every node with `clip`, playing at full weight.
+    pub fn new(clip: usize, animation: &Animation) -> Self {
+        let mut playback = Playback::new(animation.duration());
+        playback.playing = true;
+
+        Self {
+            clip,
+            playback,
+            blend: Blend::Override,
+            weight: 1.0,
+            mask: Mask::All,
+            cursor: Cursor::default(),
+        }
+    }
+
+    /// A layer adding `clip` relative to its first frame.
+    pub fn additive(clip: usize, animation: &Animation) -> Self {
+        Self {
+            blend: Blend::Additive(animation.sample(0.0)),
+            ..Self::new(clip, animation)
+        }
+    }
+
+    /// Puts the layer on top of `pose`, `animation` is the clip of the layer.
+    ///
+    /// Properties `pose` doesn't have start from their value in the `rest` pose, see
+    /// `Animations::rest`.
+    pub fn apply(&mut self, pose: &Pose, animation: &Animation, rest: &Pose) -> Pose {
+        let layer = animation
+            .sample_with(self.playback.time(), &mut self.cursor)
+            .masked(&self.mask);
+
+        match self.blend {
+            Blend::Override => pose.overlay(&layer, self.weight, rest),
+            Blend::Additive(ref reference) => {
+                pose.add(&layer.difference(reference), self.weight, rest)
+            }
+        }
+    }
+}
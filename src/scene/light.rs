@@ -0,0 +1,92 @@
+use cgmath::{Rad, Vector3};
+use gltf::khr_lights_punctual::{Kind, Light as gltfLight};
+
+/// A punctual light from the `KHR_lights_punctual` extension.
+///
+/// Lights sit at the origin of the node using them and point down its -Z axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Vector3<f32>,
+    pub intensity: f32,     // lux for directional lights, candela for the others
+    pub range: Option<f32>, // distance where the light reaches zero, `None` means infinite
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot {
+        inner_cone_angle: Rad<f32>,
+        outer_cone_angle: Rad<f32>,
+    },
+}
+
+impl LightKind {
+    /// Identifier used by the shaders.
+    #[inline]
+    pub fn id(&self) -> i32 {
+        match self {
+            LightKind::Directional => 0,
+            LightKind::Point => 1,
+            LightKind::Spot { .. } => 2,
+        }
+    }
+}
+
+impl<'a> From<gltfLight<'a>> for Light {
+    fn from(light: gltfLight<'a>) -> Self {
+        let kind = match light.kind() {
+            Kind::Directional => LightKind::Directional,
+            Kind::Point => LightKind::Point,
+            Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => LightKind::Spot {
+                inner_cone_angle: Rad(inner_cone_angle),
+                outer_cone_angle: Rad(outer_cone_angle),
+            },
+        };
+
+        Self {
+            kind,
+            color: light.color().into(),
+            intensity: light.intensity(),
+            range: light.range(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{test_util::test_asset, SceneData};
+
+    #[test]
+    fn punctual_lights() {
+        let path = test_asset("lights.gltf");
+        let data = SceneData::load(path).unwrap();
+
+        assert_eq!(data.lights.len(), 3);
+        assert_eq!(data.nodes[0].light, Some(0));
+        assert_eq!(data.nodes[1].light, Some(1));
+        assert_eq!(data.nodes[2].light, Some(2));
+
+        assert_eq!(data.lights[0].kind, LightKind::Directional);
+        assert_eq!(data.lights[0].range, None);
+
+        let point = data.lights[1];
+        assert_eq!(point.kind, LightKind::Point);
+        assert_eq!(point.color, Vector3::new(1.0, 0.5, 0.25));
+        assert_eq!(point.intensity, 20.0);
+        assert_eq!(point.range, Some(10.0));
+
+        assert_eq!(
+            data.lights[2].kind,
+            LightKind::Spot {
+                inner_cone_angle: Rad(0.25),
+                outer_cone_angle: Rad(0.5)
+            }
+        );
+    }
+}
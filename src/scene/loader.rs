@@ -0,0 +1,579 @@
+use crate::{aabb::Aabb, core::camera::Camera, ogl::material::Material};
+use cgmath::{prelude::*, Matrix4, Quaternion, Vector2, Vector3, Vector4};
+use thiserror::Error;
+
+use super::{
+    animations::Animation,
+    data::{SceneData, SceneRoots, TextureData},
+    skin::Skin,
+    Attributes, Light, MeshData, MorphTarget, Node, PrimitiveData, Vertice,
+};
+
+use gltf::json::Value;
+use std::{fs::File, io::Read, path::Path};
+
+#[derive(Debug, Error)]
+pub enum LoaderError {
+    #[error("model loader io error: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("error in model file: {0}")]
+    FileError(String),
+    #[error("gltf loader error: {0}")]
+    GltfError(#[from] gltf::Error),
+    #[error("obj loader error: {0}")]
+    ObjError(#[from] tobj::LoadError),
+    #[error("texture loader error: {0}")]
+    ImageError(#[from] image::ImageError),
+    #[error("json error: {0}")]
+    JsonError(#[from] gltf::json::Error),
+    #[error("expected {expected} buffers, got {found}")]
+    BufferCount { expected: usize, found: usize },
+    #[error("expected {expected} images, got {found}")]
+    ImageCount { expected: usize, found: usize },
+    #[error("mesh {mesh} primitive {primitive} has no positions")]
+    MissingPositions { mesh: usize, primitive: usize },
+    #[error(
+        "mesh {mesh} primitive {primitive}: accessor {accessor} indexes vertex {index} of {count}"
+    )]
+    IndexOutOfRange {
+        mesh: usize,
+        primitive: usize,
+        accessor: usize,
+        index: u32,
+        count: usize,
+    },
+    #[error(
+        "skin {skin}: accessor {accessor} has {found} inverse bind matrices for {joints} joints"
+    )]
+    BindMatrixCount {
+        skin: usize,
+        accessor: usize,
+        found: usize,
+        joints: usize,
+    },
+    #[error("animation {animation}: keyframe accessor {accessor} of node {node} is empty")]
+    MissingKeyframes {
+        animation: usize,
+        node: usize,
+        accessor: usize,
+    },
+    #[error("animation {animation}: accessor {accessor} of node {node} has {found} values for {expected} keyframes")]
+    KeyframeCount {
+        animation: usize,
+        node: usize,
+        accessor: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error("mesh {mesh} primitive {primitive}: variant {variant} maps to material {material}, which the file doesn't have")]
+    VariantMapping {
+        mesh: usize,
+        primitive: usize,
+        variant: usize,
+        material: usize,
+    },
+    #[error("state machine, line {line}: {message}")]
+    StateMachine { line: usize, message: String },
+}
+
+pub fn load_gltf<P>(path: P) -> Result<SceneData, LoaderError>
+where
+    P: AsRef<Path>,
+{
+    let (document, buffers, images) = gltf::import(&path)?;
+    let json = read_json(path.as_ref())?;
+
+    process_document(&document, &buffers, images, &json)
+}
+
+// gltf drops the extensions it doesn't support, so the JSON is also read as plain values
+fn read_json(path: &Path) -> Result<Value, LoaderError> {
+    let mut file = File::open(path)?;
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic)?;
+
+    let json = if &magic == b"glTF" {
+        // rest of the header (version, length) and of the JSON chunk header (length, type)
+        let mut header = [0; 16];
+        file.read_exact(&mut header)?;
+
+        let length = u32::from_le_bytes([header[8], header[9], header[10], header[11]]) as u64;
+        if length > file.metadata()?.len().saturating_sub(20) {
+            return Err(LoaderError::FileError(format!(
+                "GLB JSON chunk of {} bytes is longer than the file",
+                length
+            )));
+        }
+
+        let mut json = vec![0; length as usize];
+        file.read_exact(&mut json)?;
+        json
+    } else {
+        let mut json = magic.to_vec();
+        file.read_to_end(&mut json)?;
+        json
+    };
+
+    Ok(gltf::json::deserialize::from_slice(&json)?)
+}
+
+pub(crate) fn process_document(
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+    images: Vec<gltf::image::Data>,
+    json: &Value,
+) -> Result<SceneData, LoaderError> {
+    if buffers.len() != document.buffers().count() {
+        return Err(LoaderError::BufferCount {
+            expected: document.buffers().count(),
+            found: buffers.len(),
+        });
+    }
+    if images.len() != document.images().count() {
+        return Err(LoaderError::ImageCount {
+            expected: document.images().count(),
+            found: images.len(),
+        });
+    }
+
+    let materials: Vec<Material> = document
+        .materials()
+        // .par_bridge()
+        .map(|m| {
+            let mut material = Material::from(m.clone());
+            if let Some(index) = m.index() {
+                material.read_texture_transforms(&json["materials"][index]);
+                material.read_extensions(&json["materials"][index]["extensions"]);
+            }
+            material
+        })
+        .collect();
+
+    let meshes: Vec<MeshData> = document
+        .meshes()
+        .map(|mesh| process_mesh(buffers, &mesh, &json["meshes"][mesh.index()]))
+        .collect::<Result<_, _>>()?;
+
+    let nodes: Vec<Node> = document
+        .nodes()
+        .map(|node| {
+            let mut this = process_node(&node);
+            this.instances = instance_transforms(&json["nodes"][node.index()], document, buffers);
+            this
+        })
+        .collect();
+
+    let skins = document
+        .skins()
+        .map(|s| Skin::from_gltf(&s, buffers))
+        .collect::<Result<_, _>>()?;
+
+    let mut scenes: Vec<SceneRoots> = document
+        .scenes()
+        .map(|scene| {
+            let roots = scene.nodes().map(|node| node.index()).collect();
+            SceneRoots::new(scene.name().map(String::from), roots)
+        })
+        .collect();
+
+    // scenes are optional in glTF, show every parentless node when there are none
+    if scenes.is_empty() {
+        let mut is_child = vec![false; nodes.len()];
+        for node in nodes.iter() {
+            for &child in node.children.iter() {
+                is_child[child] = true;
+            }
+        }
+
+        let roots = (0..nodes.len()).filter(|&i| !is_child[i]).collect();
+        scenes.push(SceneRoots::new(None, roots));
+    }
+
+    let cameras = document.cameras().map(Camera::from).collect();
+    let lights = document
+        .lights()
+        .map_or(Vec::new(), |lights| lights.map(Light::from).collect());
+
+    let default_scene = document.default_scene().map_or(0, |scene| scene.index());
+
+    let animations = document
+        .animations()
+        .map(|anim| Animation::new(&anim, buffers))
+        .collect::<Result<_, _>>()?;
+
+    let variants = json["extensions"]["KHR_materials_variants"]["variants"]
+        .as_array()
+        .map_or(Vec::new(), |variants| {
+            variants
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    v["name"]
+                        .as_str()
+                        .map_or(format!("Variant {}", i), String::from)
+                })
+                .collect()
+        });
+
+    // the mappings come from the raw JSON, gltf doesn't check them
+    for (m, mesh) in meshes.iter().enumerate() {
+        for (p, primitive) in mesh.primitives.iter().enumerate() {
+            let bad = primitive.variants.iter().find(|&&(variant, material)| {
+                variant >= variants.len() || material >= materials.len()
+            });
+
+            if let Some(&(variant, material)) = bad {
+                return Err(LoaderError::VariantMapping {
+                    mesh: m,
+                    primitive: p,
+                    variant,
+                    material,
+                });
+            }
+        }
+    }
+
+    let textures = document
+        .textures()
+        .map(|t| TextureData {
+            image: t.source().index(),
+            sampler: t.sampler().into(),
+        })
+        .collect();
+
+    Ok(SceneData {
+        nodes,
+        scenes,
+        default_scene,
+        meshes,
+        images,
+        textures,
+        materials,
+        skins,
+        animations,
+        cameras,
+        lights,
+        variants,
+    })
+}
+
+fn process_node(node: &gltf::Node) -> Node {
+    let mesh = node.mesh().map(|m| m.index());
+    let transform = node.transform();
+    let children = node.children().map(|child| child.index()).collect();
+    let skin = node.skin().map(|s| s.index());
+
+    let mut this = Node::new(mesh, transform, children, skin);
+    this.name = node.name().map(String::from);
+    this.camera = node.camera().map(|c| c.index());
+    this.light = node.light().map(|l| l.index());
+    // the node weights override the ones from the mesh
+    this.weights = node
+        .weights()
+        .or_else(|| node.mesh().and_then(|m| m.weights()))
+        .map_or(Vec::new(), Vec::from);
+
+    this
+}
+
+// EXT_mesh_gpu_instancing, TRS accessors with the transform of each instance
+fn instance_transforms(
+    node: &Value,
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+) -> Vec<Matrix4<f32>> {
+    let attributes = &node["extensions"]["EXT_mesh_gpu_instancing"]["attributes"];
+    let read = |name: &str| {
+        let index = attributes[name].as_u64()? as usize;
+        read_floats(&document.accessors().nth(index)?, buffers)
+    };
+
+    let translations = read("TRANSLATION").unwrap_or_default();
+    let rotations = read("ROTATION").unwrap_or_default();
+    let scales = read("SCALE").unwrap_or_default();
+    let count = (translations.len() / 3)
+        .max(rotations.len() / 4)
+        .max(scales.len() / 3);
+
+    (0..count)
+        .map(|i| {
+            let translation = translations
+                .get(i * 3..i * 3 + 3)
+                .map_or(Matrix4::identity(), |t| {
+                    Matrix4::from_translation(Vector3::new(t[0], t[1], t[2]))
+                });
+            let rotation = rotations
+                .get(i * 4..i * 4 + 4)
+                .map_or(Matrix4::identity(), |r| {
+                    Matrix4::from(Quaternion::new(r[3], r[0], r[1], r[2]))
+                });
+            let scale = scales
+                .get(i * 3..i * 3 + 3)
+                .map_or(Matrix4::identity(), |s| {
+                    Matrix4::from_nonuniform_scale(s[0], s[1], s[2])
+                });
+
+            translation * rotation * scale
+        })
+        .collect()
+}
+
+// (variant, material) pairs of a primitive using KHR_materials_variants
+fn variant_mappings(primitive: &Value) -> Vec<(usize, usize)> {
+    let mappings = primitive["extensions"]["KHR_materials_variants"]["mappings"].as_array();
+
+    mappings
+        .into_iter()
+        .flatten()
+        .filter_map(|mapping| {
+            let material = mapping["material"].as_u64()? as usize;
+            let variants = mapping["variants"].as_array()?;
+
+            Some(
+                variants
+                    .iter()
+                    .filter_map(Value::as_u64)
+                    .map(move |variant| (variant as usize, material)),
+            )
+        })
+        .flatten()
+        .collect()
+}
+
+/// Reads an accessor as floats, whatever its component type, normalized integers are mapped to
+/// [0, 1] or [-1, 1].
+fn read_floats(accessor: &gltf::Accessor, buffers: &[gltf::buffer::Data]) -> Option<Vec<f32>> {
+    use gltf::accessor::{DataType, Dimensions, Iter};
+
+    let get_data = |buffer: gltf::Buffer| buffers.get(buffer.index()).map(|data| &data.0[..]);
+    let mut values = Vec::with_capacity(accessor.count() * accessor.dimensions().multiplicity());
+
+    macro_rules! read {
+        ($t:ty) => {
+            match accessor.dimensions() {
+                Dimensions::Vec2 => Iter::<[$t; 2]>::new(accessor.clone(), get_data)?
+                    .for_each(|v| values.extend(v.iter().map(|&c| c as f32))),
+                Dimensions::Vec3 => Iter::<[$t; 3]>::new(accessor.clone(), get_data)?
+                    .for_each(|v| values.extend(v.iter().map(|&c| c as f32))),
+                Dimensions::Vec4 => Iter::<[$t; 4]>::new(accessor.clone(), get_data)?
+                    .for_each(|v| values.extend(v.iter().map(|&c| c as f32))),
+                _ => {
+                    Iter::<$t>::new(accessor.clone(), get_data)?.for_each(|c| values.push(c as f32))
+                }
+            }
+        };
+    }
+
+    let max = match accessor.data_type() {
+        DataType::I8 => {
+            read!(i8);
+            i8::MAX as f32
+        }
+        DataType::U8 => {
+            read!(u8);
+            u8::MAX as f32
+        }
+        DataType::I16 => {
+            read!(i16);
+            i16::MAX as f32
+        }
+        DataType::U16 => {
+            read!(u16);
+            u16::MAX as f32
+        }
+        DataType::U32 => {
+            read!(u32);
+            u32::MAX as f32
+        }
+        DataType::F32 => {
+            read!(f32);
+            1.0
+        }
+    };
+
+    if accessor.normalized() && accessor.data_type() != DataType::F32 {
+        values.iter_mut().for_each(|v| *v = (*v / max).max(-1.0));
+    }
+
+    Some(values)
+}
+
+fn process_mesh(
+    buffers: &[gltf::buffer::Data],
+    m: &gltf::Mesh,
+    json: &Value,
+) -> Result<MeshData, LoaderError> {
+    let primitives = m
+        .primitives()
+        .map(|primitive| {
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+            // KHR_mesh_quantization allows integers for these, the reader only takes floats
+            let read = |semantic: gltf::Semantic| {
+                primitive
+                    .get(&semantic)
+                    .and_then(|accessor| read_floats(&accessor, buffers))
+            };
+
+            let positions =
+                read(gltf::Semantic::Positions).ok_or(LoaderError::MissingPositions {
+                    mesh: m.index(),
+                    primitive: primitive.index(),
+                })?;
+            let mut vertices: Vec<Vertice> = positions
+                .chunks_exact(3)
+                .map(|p| Vertice {
+                    pos: Vector3::new(p[0], p[1], p[2]),
+                    ..Vertice::default()
+                })
+                .collect();
+
+            let mut attributes = Attributes::default();
+
+            if let Some(norm) = read(gltf::Semantic::Normals) {
+                attributes.insert(Attributes::NORMAL);
+                for (v, n) in vertices.iter_mut().zip(norm.chunks_exact(3)) {
+                    v.normal = Vector3::new(n[0], n[1], n[2]);
+                }
+            }
+
+            if let Some(tex) = read(gltf::Semantic::TexCoords(0)) {
+                attributes.insert(Attributes::TEX_COORD_0);
+                for (v, uv) in vertices.iter_mut().zip(tex.chunks_exact(2)) {
+                    v.tex = Vector2::new(uv[0], uv[1]);
+                }
+            }
+
+            if let Some(tex) = read(gltf::Semantic::TexCoords(1)) {
+                attributes.insert(Attributes::TEX_COORD_1);
+                for (v, uv) in vertices.iter_mut().zip(tex.chunks_exact(2)) {
+                    v.tex1 = Vector2::new(uv[0], uv[1]);
+                }
+            }
+
+            if let Some(colors) = reader.read_colors(0) {
+                attributes.insert(Attributes::COLOR);
+                for (v, color) in vertices.iter_mut().zip(colors.into_rgba_f32()) {
+                    v.color = color.into();
+                }
+            }
+
+            if let Some(joints) = reader.read_joints(0) {
+                attributes.insert(Attributes::SKIN);
+                for (v, j) in vertices.iter_mut().zip(joints.into_u16()) {
+                    v.joints =
+                        cgmath::Vector4::new(j[0] as f32, j[1] as f32, j[2] as f32, j[3] as f32);
+                }
+            }
+
+            if let Some(w) = reader.read_weights(0) {
+                for (v, w) in vertices.iter_mut().zip(w.into_f32()) {
+                    v.weights = cgmath::Vector4::from(w);
+                }
+            }
+
+            if let Some(tangents) = read(gltf::Semantic::Tangents) {
+                attributes.insert(Attributes::TANGENT);
+                for (v, t) in vertices.iter_mut().zip(tangents.chunks_exact(4)) {
+                    v.tangent = Vector4::new(t[0], t[1], t[2], t[3]);
+                }
+            }
+
+            let indices: Vec<u32> = if let Some(ind) = reader.read_indices() {
+                ind.into_u32().collect()
+            } else {
+                vec![]
+            };
+
+            if let Some(&index) = indices.iter().find(|&&i| i as usize >= vertices.len()) {
+                return Err(LoaderError::IndexOutOfRange {
+                    mesh: m.index(),
+                    primitive: primitive.index(),
+                    accessor: primitive.indices().map_or(0, |a| a.index()),
+                    index,
+                    count: vertices.len(),
+                });
+            }
+
+            // the bounds of normalized positions are given before normalization
+            let normalized = primitive
+                .get(&gltf::Semantic::Positions)
+                .is_some_and(|accessor| accessor.normalized());
+            let bounds = if normalized {
+                Aabb::from_points(vertices.iter().map(|v| v.pos))
+            } else {
+                primitive.bounding_box().into()
+            };
+
+            let displacements = |accessor: Option<gltf::Accessor>| {
+                accessor
+                    .and_then(|accessor| read_floats(&accessor, buffers))
+                    .map_or(Vec::new(), |d| {
+                        d.chunks_exact(3)
+                            .map(|d| Vector3::new(d[0], d[1], d[2]))
+                            .collect()
+                    })
+            };
+
+            let targets = primitive
+                .morph_targets()
+                .map(|target| MorphTarget {
+                    positions: displacements(target.positions()),
+                    normals: displacements(target.normals()),
+                    tangents: displacements(target.tangents()),
+                })
+                .collect();
+
+            let mut data = PrimitiveData {
+                vertices,
+                indices,
+                material: primitive.material().index(),
+                aabb: bounds,
+                mode: primitive.mode().as_gl_enum(),
+                targets,
+                attributes,
+                variants: variant_mappings(&json["primitives"][primitive.index()]),
+            };
+
+            // the spec asks for flat normals when they're missing
+            if !attributes.contains(Attributes::NORMAL) {
+                data.generate_flat_normals();
+            }
+
+            if !attributes.contains(Attributes::TANGENT) {
+                if let Some(normal) = primitive.material().normal_texture() {
+                    data.generate_tangents(normal.tex_coord());
+                }
+            }
+
+            Ok(data)
+        })
+        .collect::<Result<_, _>>()?;
+
+    let mut mesh = MeshData::new(primitives, m.name().map(String::from));
+    mesh.weights = m.weights().map_or(Vec::new(), Vec::from);
+
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glb_chunk_length() {
+        let path = std::env::temp_dir().join(format!("glboot_chunk_{}.glb", std::process::id()));
+
+        // header claiming a JSON chunk far longer than the file
+        let mut glb = b"glTF".to_vec();
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&32u32.to_le_bytes());
+        glb.extend_from_slice(&u32::MAX.to_le_bytes());
+        glb.extend_from_slice(b"JSON{}");
+        std::fs::write(&path, glb).unwrap();
+
+        let result = read_json(&path);
+        let _ = std::fs::remove_file(&path);
+        assert!(matches!(result, Err(LoaderError::FileError(_))));
+    }
+}
@@ -1,10 +1,15 @@
 use gl::types::*;
 
 use cgmath::{Vector2, Vector3, Vector4};
+use std::cell::RefCell;
 
 use crate::{
     aabb::Aabb,
-    ogl::buffers::{array::VertexArray, index::IndexBuffer, vertex::VertexBuffer},
+    ogl::buffers::{
+        array::{Layout, VertexArray},
+        index::IndexBuffer,
+        vertex::VertexBuffer,
+    },
 };
 
 #[derive(Debug)]
@@ -14,6 +19,105 @@ pub struct Mesh {
     pub aabb: Aabb,
 }
 
+/// CPU side version of a `Mesh`, see `SceneData`.
+#[derive(Debug, Clone)]
+pub struct MeshData {
+    pub primitives: Vec<PrimitiveData>,
+    pub name: Option<String>,
+    pub aabb: Aabb,
+    pub weights: Vec<f32>, // default morph target weights
+}
+
+/// CPU side version of a `Primitive`, holds the geometry until it's uploaded to the GPU.
+#[derive(Debug, Clone)]
+pub struct PrimitiveData {
+    pub vertices: Vec<Vertice>,
+    pub indices: Vec<u32>,
+    pub material: Option<usize>,
+    pub aabb: Aabb,
+    pub mode: GLenum,
+    pub targets: Vec<MorphTarget>,
+    pub attributes: Attributes, // which vertex attributes came with the data
+    pub variants: Vec<(usize, usize)>, // (variant, material) pairs from KHR_materials_variants
+}
+
+/// Set of vertex attributes present in a primitive, the others hold `Vertice` defaults.
+///
+/// The bits are sent to the shaders in the `attributes` uniform.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Attributes(u32);
+
+impl Attributes {
+    pub const NORMAL: Self = Attributes(1);
+    pub const TEX_COORD_0: Self = Attributes(1 << 1);
+    pub const TEX_COORD_1: Self = Attributes(1 << 2);
+    pub const SKIN: Self = Attributes(1 << 3); // joints and weights
+    pub const TANGENT: Self = Attributes(1 << 4);
+    pub const COLOR: Self = Attributes(1 << 5);
+
+    #[inline]
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    #[inline]
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    #[inline]
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Layout of vertices packed by `pack_vertices`.
+    ///
+    /// Every attribute keeps its location in the shaders whether the others are there or not:
+    /// 0 position, 1 normal, 2 first UV set, 3 joints, 4 weights, 5 tangent, 6 color, 7 second UV set.
+    pub fn layout(self) -> Layout {
+        let mut layout = Layout::default();
+
+        layout.push_at::<f32>(0, 3, gl::FLOAT, false);
+        if self.contains(Self::NORMAL) {
+            layout.push_at::<f32>(1, 3, gl::FLOAT, false);
+        }
+        if self.contains(Self::TEX_COORD_0) {
+            layout.push_at::<f32>(2, 2, gl::FLOAT, false);
+        }
+        if self.contains(Self::SKIN) {
+            layout.push_at::<u16>(3, 4, gl::UNSIGNED_SHORT, false);
+            layout.push_at::<u16>(4, 4, gl::UNSIGNED_SHORT, true);
+        }
+        if self.contains(Self::TANGENT) {
+            layout.push_at::<f32>(5, 4, gl::FLOAT, false);
+        }
+        if self.contains(Self::COLOR) {
+            layout.push_at::<u8>(6, 4, gl::UNSIGNED_BYTE, true);
+        }
+        if self.contains(Self::TEX_COORD_1) {
+            layout.push_at::<f32>(7, 2, gl::FLOAT, false);
+        }
+
+        layout
+    }
+}
+
+impl std::ops::BitOr for Attributes {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Attributes(self.0 | other.0)
+    }
+}
+
+/// Per vertex displacements of a morph target, an attribute the target doesn't affect is left empty.
+#[derive(Debug, Clone, Default)]
+pub struct MorphTarget {
+    pub positions: Vec<Vector3<f32>>,
+    pub normals: Vec<Vector3<f32>>,
+    pub tangents: Vec<Vector3<f32>>,
+}
+
 #[derive(Debug)]
 pub struct Primitive {
     // vertices: Vec<Vertice>, // should I keep them here?
@@ -24,8 +128,20 @@ pub struct Primitive {
     pub indices_count: i32,
     pub vertice_count: i32,
     pub ibo: IndexBuffer,
-    aabb: Aabb,
+    pub index_type: GLenum, // gl::UNSIGNED_SHORT when every index fits
+    pub aabb: Aabb,
     pub mode: GLenum,
+    pub attributes: Attributes,
+    pub variants: Vec<(usize, usize)>, // (variant, material)
+    morph: Option<Morph>,
+}
+
+// the morph targets are blended on the CPU, so the undeformed vertices must be kept around
+#[derive(Debug)]
+struct Morph {
+    base: Vec<Vertice>,
+    targets: Vec<MorphTarget>,
+    applied: RefCell<Vec<f32>>, // weights currently in the vertex buffer
 }
 
 #[repr(C)]
@@ -37,6 +153,8 @@ pub struct Vertice {
     pub joints: Vector4<f32>,
     pub weights: Vector4<f32>,
     pub tangent: Vector4<f32>,
+    pub color: Vector4<f32>,
+    pub tex1: Vector2<f32>, // second UV set, usually lightmaps
 }
 
 impl Mesh {
@@ -53,6 +171,46 @@ impl Mesh {
     }
 }
 
+impl MeshData {
+    pub fn new(primitives: Vec<PrimitiveData>, name: Option<String>) -> Self {
+        let aabb = primitives
+            .iter()
+            .fold(Aabb::default(), |bound, prim| bound.surrounds(&prim.aabb));
+
+        Self {
+            primitives,
+            name,
+            aabb,
+            weights: Vec::new(),
+        }
+    }
+}
+
+impl From<MeshData> for Mesh {
+    fn from(data: MeshData) -> Self {
+        let primitives = data.primitives.into_iter().map(Primitive::from).collect();
+
+        Mesh::new(primitives, data.name)
+    }
+}
+
+impl From<PrimitiveData> for Primitive {
+    fn from(data: PrimitiveData) -> Self {
+        let mut primitive = Primitive::setup(
+            data.vertices,
+            data.indices,
+            data.material,
+            data.aabb,
+            data.mode,
+            data.targets,
+            data.attributes,
+        );
+        primitive.variants = data.variants;
+
+        primitive
+    }
+}
+
 impl Primitive {
     pub fn setup(
         vertices: Vec<Vertice>,
@@ -60,35 +218,179 @@ impl Primitive {
         material: Option<usize>,
         aabb: Aabb,
         mode: GLenum,
+        targets: Vec<MorphTarget>,
+        attributes: Attributes,
     ) -> Self {
         let vertice_count = vertices.len() as i32;
         let indices_count = indices.len() as i32;
-        let vbo = VertexBuffer::new(&vertices);
-        let ibo = IndexBuffer::new(&indices);
-        let vao = VertexArray::new();
 
-        let layout = layout![
-            (3, f32, gl::FLOAT),
-            (3, f32, gl::FLOAT),
-            (2, f32, gl::FLOAT),
-            (4, f32, gl::FLOAT),
-            (4, f32, gl::FLOAT),
-            (4, f32, gl::FLOAT)
-        ];
+        // only the attributes the primitive has are uploaded
+        let packed = pack_vertices(&vertices, attributes);
+        let vbo = if targets.is_empty() {
+            VertexBuffer::new(&packed)
+        } else {
+            VertexBuffer::dynamic(&packed)
+        };
+
+        let (ibo, index_type) = if indices.iter().all(|&i| i <= u16::MAX as u32) {
+            let short: Vec<u16> = indices.iter().map(|&i| i as u16).collect();
+            (IndexBuffer::new(&short), gl::UNSIGNED_SHORT)
+        } else {
+            (IndexBuffer::new(&indices), gl::UNSIGNED_INT)
+        };
 
-        vao.add_buffer(&vbo, &layout);
+        let vao = VertexArray::new();
+        vao.add_buffer(&vbo, &attributes.layout());
+
+        let morph = if targets.is_empty() {
+            None
+        } else {
+            Some(Morph {
+                base: vertices,
+                targets,
+                applied: RefCell::new(Vec::new()),
+            })
+        };
 
         Self {
             vbo,
             ibo,
+            index_type,
             vao,
             vertice_count,
             indices_count,
             material,
             aabb,
             mode,
+            attributes,
+            variants: Vec::new(),
+            morph,
         }
     }
+
+    /// Material used when `variant` is active, the default one if there's no mapping for it.
+    pub fn variant_material(&self, variant: Option<usize>) -> Option<usize> {
+        variant_material(&self.variants, variant).or(self.material)
+    }
+
+    /// Blends the morph targets with `weights` and uploads the result.
+    ///
+    /// Does nothing for primitives without targets or if the weights didn't change since the last call.
+    pub fn morph(&self, weights: &[f32]) {
+        if let Some(ref morph) = self.morph {
+            let mut applied = morph.applied.borrow_mut();
+
+            if applied.as_slice() != weights {
+                let vertices = blend_targets(&morph.base, &morph.targets, weights);
+                self.vbo
+                    .write(0, &pack_vertices(&vertices, self.attributes));
+
+                applied.clear();
+                applied.extend_from_slice(weights);
+            }
+        }
+    }
+}
+
+impl PrimitiveData {
+    /// Material used when `variant` is active.
+    pub fn variant_material(&self, variant: Option<usize>) -> Option<usize> {
+        variant_material(&self.variants, variant).or(self.material)
+    }
+}
+
+fn variant_material(variants: &[(usize, usize)], variant: Option<usize>) -> Option<usize> {
+    let variant = variant?;
+
+    variants
+        .iter()
+        .find(|&&(v, _)| v == variant)
+        .map(|&(_, material)| material)
+}
+
+/// Layout of the instance transforms, a column of the matrix in each of the locations 8 to 11.
+pub fn instance_layout() -> Layout {
+    let mut layout = Layout::default();
+
+    for column in 0..4 {
+        layout.push_instanced::<f32>(8 + column, 4, gl::FLOAT, 1);
+    }
+
+    layout
+}
+
+/// Interleaves the `attributes` of the vertices following `Attributes::layout`.
+///
+/// Joints are stored as u16, weights as normalized u16 and colors as normalized u8.
+pub fn pack_vertices(vertices: &[Vertice], attributes: Attributes) -> Vec<u8> {
+    let stride = attributes.layout().stride() as usize;
+    let mut packed = Vec::with_capacity(vertices.len() * stride);
+
+    let floats = |packed: &mut Vec<u8>, values: &[f32]| {
+        values
+            .iter()
+            .for_each(|v| packed.extend_from_slice(&v.to_ne_bytes()))
+    };
+
+    for v in vertices.iter() {
+        floats(&mut packed, v.pos.as_ref() as &[f32; 3]);
+
+        if attributes.contains(Attributes::NORMAL) {
+            floats(&mut packed, v.normal.as_ref() as &[f32; 3]);
+        }
+        if attributes.contains(Attributes::TEX_COORD_0) {
+            floats(&mut packed, v.tex.as_ref() as &[f32; 2]);
+        }
+        if attributes.contains(Attributes::SKIN) {
+            let joints: &[f32; 4] = v.joints.as_ref();
+            for &j in joints.iter() {
+                packed.extend_from_slice(&(j as u16).to_ne_bytes());
+            }
+
+            let weights: &[f32; 4] = v.weights.as_ref();
+            for &w in weights.iter() {
+                let w = (w.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16;
+                packed.extend_from_slice(&w.to_ne_bytes());
+            }
+        }
+        if attributes.contains(Attributes::TANGENT) {
+            floats(&mut packed, v.tangent.as_ref() as &[f32; 4]);
+        }
+        if attributes.contains(Attributes::COLOR) {
+            let color: &[f32; 4] = v.color.as_ref();
+            for &c in color.iter() {
+                packed.push((c.clamp(0.0, 1.0) * u8::MAX as f32).round() as u8);
+            }
+        }
+        if attributes.contains(Attributes::TEX_COORD_1) {
+            floats(&mut packed, v.tex1.as_ref() as &[f32; 2]);
+        }
+    }
+
+    packed
+}
+
+/// Applies the weighted displacements of `targets` to `base`.
+pub fn blend_targets(base: &[Vertice], targets: &[MorphTarget], weights: &[f32]) -> Vec<Vertice> {
+    let mut vertices = base.to_vec();
+
+    for (target, &weight) in targets.iter().zip(weights.iter()) {
+        if weight == 0.0 {
+            continue;
+        }
+
+        for (v, p) in vertices.iter_mut().zip(target.positions.iter()) {
+            v.pos += p * weight;
+        }
+        for (v, n) in vertices.iter_mut().zip(target.normals.iter()) {
+            v.normal += n * weight;
+        }
+        for (v, t) in vertices.iter_mut().zip(target.tangents.iter()) {
+            v.tangent += (t * weight).extend(0.0);
+        }
+    }
+
+    vertices
 }
 
 impl Default for Vertice {
@@ -100,6 +402,70 @@ impl Default for Vertice {
             joints: Vector4::new(0.0, 0.0, 0.0, 0.0),
             weights: Vector4::new(0.0, 0.0, 0.0, 0.0),
             tangent: Vector4::new(0.0, 0.0, 0.0, 0.0),
+            color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            tex1: Vector2::new(0.0, 0.0),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn morph_blending() {
+        let base = vec![Vertice::default(); 2];
+        let targets = vec![
+            MorphTarget {
+                positions: vec![Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 2.0, 0.0)],
+                ..MorphTarget::default()
+            },
+            MorphTarget {
+                positions: vec![Vector3::new(0.0, 0.0, 4.0), Vector3::new(0.0, 0.0, 0.0)],
+                normals: vec![Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 1.0, 0.0)],
+                ..MorphTarget::default()
+            },
+        ];
+
+        let blended = blend_targets(&base, &targets, &[0.5, 0.25]);
+
+        assert_eq!(blended[0].pos, Vector3::new(0.5, 0.0, 1.0));
+        assert_eq!(blended[1].pos, Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(blended[1].normal, Vector3::new(0.0, 0.25, 0.0));
+
+        // missing weights leave the vertices untouched
+        let blended = blend_targets(&base, &targets, &[]);
+        assert_eq!(blended[0].pos, base[0].pos);
+    }
+
+    #[test]
+    fn packed_layouts() {
+        let vertex = Vertice {
+            pos: Vector3::new(1.0, 2.0, 3.0),
+            joints: Vector4::new(1.0, 0.0, 300.0, 0.0),
+            weights: Vector4::new(1.0, 0.0, 0.5, 0.0),
+            color: Vector4::new(1.0, 0.0, 0.5, 1.0),
+            ..Vertice::default()
+        };
+
+        // positions only
+        let layout = Attributes::default().layout();
+        assert_eq!(layout.stride(), 12);
+        assert_eq!(pack_vertices(&[vertex; 2], Attributes::default()).len(), 24);
+
+        let attributes = Attributes::TEX_COORD_1 | Attributes::SKIN | Attributes::COLOR;
+        let layout = attributes.layout();
+        let locations: Vec<u32> = layout.elements().iter().map(|e| e.location).collect();
+        assert_eq!(locations, vec![0, 3, 4, 6, 7]);
+        assert_eq!(layout.stride(), 12 + 8 + 8 + 4 + 8);
+
+        let packed = pack_vertices(&[vertex], attributes);
+        assert_eq!(packed.len(), layout.stride() as usize);
+
+        let short = |at: usize| u16::from_ne_bytes([packed[at], packed[at + 1]]);
+        assert_eq!(short(12 + 4), 300);
+        assert_eq!(short(20), u16::MAX);
+        assert_eq!(short(24), 32768);
+        assert_eq!(&packed[28..32], &[255, 0, 128, 255]);
+    }
+}
@@ -1,9 +1,44 @@
 mod animations;
+mod data;
+mod export;
+mod geometry;
+mod graph;
+mod layer;
+pub mod light;
+mod loader;
 pub mod mesh;
 pub mod node;
+mod obj;
+mod playback;
+mod ply;
+mod pose;
 mod scene;
 pub mod skin;
+mod state_machine;
+mod world;
 
+pub use animations::{Animation, Animations, Cursor, Mode};
+pub use data::{Sampler, SceneData, SceneRoots, TextureData};
+pub use graph::GraphError;
+pub use layer::{Blend, Layer, Mask};
+pub use light::{Light, LightKind};
+pub use loader::LoaderError;
 pub use mesh::*;
 pub use node::Node;
-pub use scene::{LoaderError, Scene};
+pub use playback::{Playback, Wrap};
+pub use pose::Pose;
+pub use scene::Scene;
+pub use state_machine::{Condition, Motion, Param, State, StateMachine, Transition};
+pub use world::{Placement, World};
+
+#[cfg(test)]
+pub(crate) mod test_util {
+    /// Path of a file in `assets/models/tests`.
+    pub fn test_asset(name: &str) -> String {
+        format!(
+            "{}/assets/models/tests/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        )
+    }
+}
@@ -1,12 +1,11 @@
-use cgmath::{Matrix4, Quaternion, Vector3};
+use cgmath::{prelude::*, Matrix3, Matrix4, Quaternion, Vector3};
 
 use crate::aabb::Aabb;
 
-use super::Mesh;
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Node {
-    pub mesh: Option<Mesh>,
+    pub name: Option<String>,
+    pub mesh: Option<usize>, // index of the mesh, see the Scene struct
 
     pub translation: Vector3<f32>,
     pub rotation: Quaternion<f32>,
@@ -16,13 +15,18 @@ pub struct Node {
     pub transform: Matrix4<f32>, // cached local transformation
 
     pub skin: Option<usize>,
-    pub children: Vec<usize>, // the indices of this node children, see the Scene struct
-                              //camera: Camera ???
+    pub camera: Option<usize>, // index of the camera, placed at this node global transform
+    pub light: Option<usize>,  // index of the light, see the Scene struct
+    pub weights: Vec<f32>,     // morph target weights of the mesh
+    pub children: Vec<usize>,  // the indices of this node children, see the Scene struct
+
+    // transforms of each instance of the mesh relative to the node, empty when it isn't instanced
+    pub instances: Vec<Matrix4<f32>>,
 }
 
 impl Node {
     pub fn new(
-        mesh: Option<Mesh>,
+        mesh: Option<usize>,
         transform: gltf::scene::Transform,
         children: Vec<usize>,
         skin: Option<usize>,
@@ -31,10 +35,15 @@ impl Node {
         let transform = transform.matrix().into();
 
         Self {
+            name: None,
             mesh,
             transform,
             children,
             skin,
+            camera: None,
+            light: None,
+            weights: Vec::new(),
+            instances: Vec::new(),
             global_transform: transform,
             translation: translation.into(),
             rotation: Quaternion::new(rotation[3], rotation[0], rotation[1], rotation[2]),
@@ -42,6 +51,17 @@ impl Node {
         }
     }
 
+    /// A childless node with an identity transform, used by loaders of formats without a hierarchy.
+    pub fn with_mesh(mesh: usize) -> Self {
+        let transform = gltf::scene::Transform::Decomposed {
+            translation: [0.0, 0.0, 0.0],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            scale: [1.0, 1.0, 1.0],
+        };
+
+        Self::new(Some(mesh), transform, Vec::new(), None)
+    }
+
     pub fn update_global(&mut self, parent: Matrix4<f32>) {
         self.global_transform = parent * self.transform;
     }
@@ -53,15 +73,32 @@ impl Node {
         self.transform = transform;
     }
 
-    pub fn gen_aabb(&self, nodes: &[Node], transform: Matrix4<f32>) -> Aabb {
-        let mut this_aabb = if let Some(ref mesh) = self.mesh {
-            mesh.aabb.transform(&transform)
-        } else {
-            Aabb::default()
+    /// Replaces the local transform, it's decomposed into translation, rotation and scale.
+    pub fn set_transform(&mut self, transform: Matrix4<f32>) {
+        let (translation, rotation, scale) = decompose(transform);
+        self.translation = translation;
+        self.rotation = rotation;
+        self.scale = scale;
+        self.update();
+    }
+
+    /// Generates the bounding box of this node and its children, `bounds` holds the AABB of each mesh.
+    pub fn gen_aabb(&self, nodes: &[Node], bounds: &[Aabb], transform: Matrix4<f32>) -> Aabb {
+        let this_transform = transform * self.transform;
+        let mut this_aabb = match self.mesh {
+            Some(mesh) if !self.instances.is_empty() => {
+                self.instances
+                    .iter()
+                    .fold(Aabb::default(), |aabb, instance| {
+                        aabb.surrounds(&bounds[mesh].transform(&(this_transform * instance)))
+                    })
+            }
+            Some(mesh) => bounds[mesh].transform(&this_transform),
+            None => Aabb::default(),
         };
 
         for &child in self.children.iter() {
-            let child_aabb = nodes[child].gen_aabb(nodes, transform * self.transform);
+            let child_aabb = nodes[child].gen_aabb(nodes, bounds, this_transform);
 
             this_aabb = this_aabb.surrounds(&child_aabb);
         }
@@ -70,6 +107,74 @@ impl Node {
     }
 }
 
+/// Depth first iterator over the nodes below a node, see `Scene::descendants`.
+pub struct Descendants<'a> {
+    nodes: &'a [Node],
+    stack: Vec<usize>,
+}
+
+impl<'a> Descendants<'a> {
+    pub fn new(nodes: &'a [Node], node: usize) -> Self {
+        Self {
+            nodes,
+            stack: nodes[node].children.iter().rev().copied().collect(),
+        }
+    }
+}
+
+impl Iterator for Descendants<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let node = self.stack.pop()?;
+        self.stack
+            .extend(self.nodes[node].children.iter().rev().copied());
+
+        Some(node)
+    }
+}
+
+/// Splits a transform into translation, rotation and scale, shears are lost.
+pub fn decompose(transform: Matrix4<f32>) -> (Vector3<f32>, Quaternion<f32>, Vector3<f32>) {
+    let columns = [
+        transform.x.truncate(),
+        transform.y.truncate(),
+        transform.z.truncate(),
+    ];
+    let mut scale = Vector3::new(
+        columns[0].magnitude(),
+        columns[1].magnitude(),
+        columns[2].magnitude(),
+    );
+    // mirrored transforms get a negative scale on X
+    if transform.determinant() < 0.0 {
+        scale.x = -scale.x;
+    }
+
+    let rotation = Quaternion::from(Matrix3::from_cols(
+        columns[0] / scale.x,
+        columns[1] / scale.y,
+        columns[2] / scale.z,
+    ))
+    .normalize();
+
+    (transform.w.truncate(), rotation, scale)
+}
+
+/// Follows a path of node names starting at one of `roots`, see `Scene::find_path`.
+pub fn find_path(nodes: &[Node], roots: &[usize], path: &str) -> Option<usize> {
+    let named = |among: &[usize], name: &str| {
+        among
+            .iter()
+            .copied()
+            .find(|&node| nodes[node].name.as_deref() == Some(name))
+    };
+    let mut names = path.split('/').filter(|name| !name.is_empty());
+
+    let root = named(roots, names.next()?)?;
+    names.try_fold(root, |node, name| named(&nodes[node].children, name))
+}
+
 pub fn build_tree(nodes: &[Node], roots: &[usize]) -> Vec<(usize, Option<usize>)> {
     let mut ret = Vec::with_capacity(nodes.len());
 
@@ -93,3 +198,49 @@ fn build_tree_helper(
         build_tree_helper(nodes, child, Some(current), indices);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Car -> Body -> (Wheel_FL, Wheel_FR), plus a second root called Body
+    fn car() -> Vec<Node> {
+        let names = ["Car", "Body", "Wheel_FL", "Wheel_FR", "Body"];
+        let children = [vec![1], vec![2, 3], vec![], vec![], vec![]];
+
+        names
+            .iter()
+            .zip(children.iter())
+            .map(|(name, children)| {
+                let mut node = Node::with_mesh(0);
+                node.name = Some(name.to_string());
+                node.children = children.clone();
+                node
+            })
+            .collect()
+    }
+
+    #[test]
+    fn paths() {
+        let nodes = car();
+        let roots = [0, 4];
+
+        assert_eq!(find_path(&nodes, &roots, "Car/Body/Wheel_FL"), Some(2));
+        assert_eq!(find_path(&nodes, &roots, "/Car/Body/"), Some(1));
+        assert_eq!(find_path(&nodes, &roots, "Body"), Some(4));
+        assert_eq!(find_path(&nodes, &roots, "Car/Wheel_FL"), None);
+        assert_eq!(find_path(&nodes, &roots, ""), None);
+    }
+
+    #[test]
+    fn descendants() {
+        let nodes = car();
+
+        assert_eq!(
+            Descendants::new(&nodes, 0).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(Descendants::new(&nodes, 1).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(Descendants::new(&nodes, 4).count(), 0);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::{
+    aabb::Aabb,
+    ogl::material::{AlphaMode, Material},
+};
+use cgmath::{Vector2, Vector3, Vector4};
+
+use super::{
+    data::{SceneData, SceneRoots, TextureData},
+    loader::LoaderError,
+    Attributes, MeshData, Node, PrimitiveData, Vertice,
+};
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Loads a Wavefront OBJ file and the MTL libraries it references.
+///
+/// Every object in the file becomes a root node with a single primitive mesh.
+pub fn load_obj<P>(path: P) -> Result<SceneData, LoaderError>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let (models, mtl) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)?;
+
+    // a missing or broken MTL shouldn't stop the geometry from loading
+    let mtl = mtl.unwrap_or_default();
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut images = Images::default();
+    let materials: Vec<Material> = mtl
+        .iter()
+        .map(|mat| process_material(mat, base, &mut images))
+        .collect();
+
+    let meshes: Vec<MeshData> = models.into_iter().map(process_model).collect();
+    let nodes: Vec<Node> = (0..meshes.len()).map(Node::with_mesh).collect();
+    let roots = (0..nodes.len()).collect();
+
+    Ok(SceneData {
+        nodes,
+        scenes: vec![SceneRoots::new(None, roots)],
+        default_scene: 0,
+        meshes,
+        textures: (0..images.data.len()).map(TextureData::new).collect(),
+        images: images.data,
+        materials,
+        skins: Vec::new(),
+        animations: Vec::new(),
+        cameras: Vec::new(),
+        lights: Vec::new(),
+        variants: Vec::new(),
+    })
+}
+
+fn process_model(model: tobj::Model) -> MeshData {
+    let mesh = model.mesh;
+
+    let vertices: Vec<Vertice> = mesh
+        .positions
+        .chunks_exact(3)
+        .enumerate()
+        .map(|(i, p)| {
+            let normal = mesh
+                .normals
+                .get(i * 3..i * 3 + 3)
+                .map_or(Vector3::new(0.0, 0.0, 0.0), |n| {
+                    Vector3::new(n[0], n[1], n[2])
+                });
+            let tex = mesh
+                .texcoords
+                .get(i * 2..i * 2 + 2)
+                .map_or(Vector2::new(0.0, 0.0), |t| Vector2::new(t[0], t[1]));
+
+            Vertice {
+                pos: Vector3::new(p[0], p[1], p[2]),
+                normal,
+                tex,
+                ..Vertice::default()
+            }
+        })
+        .collect();
+
+    let mut attributes = Attributes::default();
+    if !mesh.normals.is_empty() {
+        attributes.insert(Attributes::NORMAL);
+    }
+    if !mesh.texcoords.is_empty() {
+        attributes.insert(Attributes::TEX_COORD_0);
+    }
+
+    let aabb = Aabb::from_points(vertices.iter().map(|v| v.pos));
+    let mut primitive = PrimitiveData {
+        vertices,
+        indices: mesh.indices,
+        material: mesh.material_id,
+        aabb,
+        mode: gl::TRIANGLES,
+        targets: Vec::new(),
+        attributes,
+        variants: Vec::new(),
+    };
+    // without `vn` lines every face is flat shaded
+    if !primitive.attributes.contains(Attributes::NORMAL) {
+        primitive.generate_flat_normals();
+    }
+
+    let name = if model.name.is_empty() {
+        None
+    } else {
+        Some(model.name)
+    };
+
+    MeshData::new(vec![primitive], name)
+}
+
+// MTL is a Phong model, so the mapping to metallic-roughness is only an approximation:
+// the specular intensity drives the metalness and the shininess the roughness
+fn process_material(mat: &tobj::Material, base: &Path, images: &mut Images) -> Material {
+    let [r, g, b] = mat.diffuse;
+    let specular = mat.specular.iter().cloned().fold(0.0_f32, f32::max);
+
+    Material {
+        base_color: Vector4::new(r, g, b, mat.dissolve),
+        base_tex: images.load(base, &mat.diffuse_texture),
+        metallic: specular.min(1.0),
+        roughness: (2.0 / (mat.shininess + 2.0)).sqrt(),
+        normal: images.load(base, &mat.normal_texture),
+        alpha_mode: if mat.dissolve < 1.0 {
+            AlphaMode::Blend
+        } else {
+            AlphaMode::Opaque
+        },
+        ..Material::default()
+    }
+}
+
+// MTL files often reuse the same image in several materials, load each one only once
+#[derive(Default)]
+struct Images {
+    data: Vec<gltf::image::Data>,
+    cache: HashMap<PathBuf, usize>,
+}
+
+impl Images {
+    // a texture that can't be read is left out, the rest of the model still loads
+    fn load(&mut self, base: &Path, name: &str) -> Option<usize> {
+        if name.is_empty() {
+            return None;
+        }
+
+        let path = base.join(name);
+        if let Some(&index) = self.cache.get(&path) {
+            return Some(index);
+        }
+
+        let image = match image::open(&path) {
+            Ok(image) => image,
+            Err(err) => {
+                eprintln!("skipping texture {}: {}", path.display(), err);
+                return None;
+            }
+        };
+        // OBJ texture coordinates start at the bottom of the image
+        let image = image.flipv().into_rgba8();
+        let (width, height) = image.dimensions();
+
+        self.data.push(gltf::image::Data {
+            pixels: image.into_raw(),
+            format: gltf::image::Format::R8G8B8A8,
+            width,
+            height,
+        });
+        self.cache.insert(path, self.data.len() - 1);
+
+        Some(self.data.len() - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::test_util::test_asset;
+
+    #[test]
+    fn obj_geometry() {
+        let path = format!("{}/assets/models/cube.obj", env!("CARGO_MANIFEST_DIR"));
+        let data = load_obj(path).unwrap();
+
+        assert_eq!(data.nodes.len(), 1);
+        assert_eq!(data.roots(), &[0]);
+        assert!(data.materials.is_empty());
+
+        let mesh = &data.meshes[0];
+        assert_eq!(mesh.name.as_deref(), Some("cube"));
+        assert_eq!(mesh.primitives[0].indices.len(), 36);
+        assert_eq!(mesh.primitives[0].material, None);
+    }
+
+    #[test]
+    fn mtl_mapping() {
+        let path = test_asset("quad.obj");
+        let data = load_obj(path).unwrap();
+
+        // the quad gets triangulated
+        let prim = &data.meshes[0].primitives[0];
+        assert_eq!(prim.indices.len(), 6);
+        assert_eq!(prim.material, Some(0));
+        assert_eq!(prim.vertices[0].normal, Vector3::new(0.0, 0.0, 1.0));
+
+        let mat = &data.materials[0];
+        assert_eq!(mat.base_color, Vector4::new(0.5, 0.25, 1.0, 0.5));
+        assert_eq!(mat.metallic, 0.4);
+        assert!((mat.roughness - 0.2).abs() < 1e-6);
+        // map_Kd and map_Bump point to the same file
+        assert_eq!(mat.base_tex, Some(0));
+        assert_eq!(mat.normal, Some(0));
+        assert_eq!(data.images.len(), 1);
+
+        // flipped, so the bottom (blue) row comes first
+        assert_eq!(&data.images[0].pixels[0..4], &[0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn missing_texture() {
+        let path = test_asset("flat.obj");
+        let data = load_obj(path).unwrap();
+
+        // map_Kd can't be read, map_Bump still loads
+        let mat = &data.materials[0];
+        assert_eq!(mat.base_tex, None);
+        assert_eq!(mat.normal, Some(0));
+        assert_eq!(data.images.len(), 1);
+    }
+
+    #[test]
+    fn flat_normals() {
+        let path = test_asset("flat.obj");
+        let data = load_obj(path).unwrap();
+
+        // no `vn`, the faces get their own normals
+        let prim = &data.meshes[0].primitives[0];
+        assert!(prim.attributes.contains(Attributes::NORMAL));
+        assert_eq!(prim.vertices.len(), 6);
+        assert_eq!(prim.vertices[0].normal, Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(prim.vertices[5].normal, Vector3::new(-1.0, 0.0, 0.0));
+    }
+}
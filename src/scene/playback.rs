@@ -0,0 +1,169 @@
+/// What the clip time does once it goes past either end of the clip.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Wrap {
+    Loop,     // starts over from the other end
+    Once,     // stops and goes back to where it started
+    Clamp,    // holds the pose at the end
+    PingPong, // bounces between both ends
+}
+
+impl Wrap {
+    pub const ALL: [Wrap; 4] = [Wrap::Loop, Wrap::Once, Wrap::Clamp, Wrap::PingPong];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Wrap::Loop => "Loop",
+            Wrap::Once => "Once",
+            Wrap::Clamp => "Clamp",
+            Wrap::PingPong => "Ping-pong",
+        }
+    }
+}
+
+/// Playback of a clip, the only place the clip time is kept.
+#[derive(Debug, Copy, Clone)]
+pub struct Playback {
+    pub speed: f32, // negative speeds play backwards
+    pub wrap: Wrap,
+    pub playing: bool,
+
+    duration: f32,
+    elapsed: f32, // goes up to twice the duration when ping-ponging, see `time`
+}
+
+impl Playback {
+    pub fn new(duration: f32) -> Self {
+        Self {
+            speed: 1.0,
+            wrap: Wrap::Loop,
+            playing: false,
+            duration: duration.max(0.0),
+            elapsed: 0.0,
+        }
+    }
+
+    #[inline]
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    /// Changes the length of the clip, keeping the time inside it.
+    pub fn set_duration(&mut self, duration: f32) {
+        self.duration = duration.max(0.0);
+        self.seek(self.time());
+    }
+
+    /// Current time in the clip, between zero and the duration.
+    pub fn time(&self) -> f32 {
+        if self.elapsed > self.duration {
+            2.0 * self.duration - self.elapsed
+        } else {
+            self.elapsed
+        }
+    }
+
+    /// Jumps to `time`, clamped to the clip.
+    pub fn seek(&mut self, time: f32) {
+        self.elapsed = time.clamp(0.0, self.duration);
+    }
+
+    /// Goes back to the start, the end of the clip when playing backwards.
+    pub fn rewind(&mut self) {
+        self.elapsed = if self.speed < 0.0 { self.duration } else { 0.0 };
+    }
+
+    /// Flips the direction of the playback.
+    pub fn reverse(&mut self) {
+        self.speed = -self.speed;
+    }
+
+    /// Moves the clip time by `delta` seconds scaled by the speed, if playing.
+    pub fn advance(&mut self, delta: f32) {
+        if !self.playing {
+            return;
+        }
+
+        let duration = self.duration;
+        if duration <= 0.0 {
+            self.elapsed = 0.0;
+            return;
+        }
+
+        let elapsed = self.elapsed + delta * self.speed;
+        self.elapsed = match self.wrap {
+            Wrap::Loop => elapsed.rem_euclid(duration),
+            Wrap::PingPong => elapsed.rem_euclid(2.0 * duration),
+            Wrap::Clamp => elapsed.clamp(0.0, duration),
+            Wrap::Once if (0.0..=duration).contains(&elapsed) => elapsed,
+            Wrap::Once => {
+                self.playing = false;
+                if self.speed < 0.0 {
+                    duration
+                } else {
+                    0.0
+                }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing(wrap: Wrap) -> Playback {
+        let mut playback = Playback::new(2.0);
+        playback.wrap = wrap;
+        playback.playing = true;
+        playback
+    }
+
+    #[test]
+    fn wrap_modes() {
+        let mut playback = playing(Wrap::Loop);
+        playback.advance(2.5);
+        assert_eq!(playback.time(), 0.5);
+        playback.reverse();
+        playback.advance(1.0);
+        assert_eq!(playback.time(), 1.5);
+
+        let mut playback = playing(Wrap::Clamp);
+        playback.advance(3.0);
+        assert_eq!(playback.time(), 2.0);
+        assert!(playback.playing);
+
+        let mut playback = playing(Wrap::Once);
+        playback.advance(1.5);
+        assert_eq!(playback.time(), 1.5);
+        playback.advance(1.0);
+        assert_eq!(playback.time(), 0.0);
+        assert!(!playback.playing);
+
+        let mut playback = playing(Wrap::PingPong);
+        playback.advance(2.5);
+        assert_eq!(playback.time(), 1.5);
+        playback.advance(2.0);
+        assert_eq!(playback.time(), 0.5);
+    }
+
+    #[test]
+    fn seek_and_speed() {
+        let mut playback = playing(Wrap::Loop);
+        playback.seek(5.0);
+        assert_eq!(playback.time(), 2.0);
+
+        playback.seek(1.0);
+        playback.speed = 0.5;
+        playback.advance(1.0);
+        assert_eq!(playback.time(), 1.5);
+
+        // paused playback doesn't move
+        playback.playing = false;
+        playback.advance(1.0);
+        assert_eq!(playback.time(), 1.5);
+
+        playback.speed = -1.0;
+        playback.rewind();
+        assert_eq!(playback.time(), 2.0);
+    }
+}
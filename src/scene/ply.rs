@@ -0,0 +1,554 @@
+use crate::aabb::Aabb;
+use cgmath::{Vector2, Vector3, Vector4};
+
+use super::{
+    data::{SceneData, SceneRoots},
+    loader::LoaderError,
+    Attributes, MeshData, Node, PrimitiveData, Vertice,
+};
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+/// Loads a PLY file, ASCII and both binary encodings are supported.
+///
+/// Files with faces become a triangle mesh, files with only vertices become a point cloud.
+pub fn load_ply<P>(path: P) -> Result<SceneData, LoaderError>
+where
+    P: AsRef<Path>,
+{
+    let file = BufReader::new(File::open(path)?);
+
+    parse_ply(file)
+}
+
+fn parse_ply<R: BufRead>(mut reader: R) -> Result<SceneData, LoaderError> {
+    let header = Header::parse(&mut reader)?;
+
+    let mut body = Vec::new();
+    reader.read_to_end(&mut body)?;
+    let body_len = body.len();
+
+    let text;
+    let mut data = match header.format {
+        Format::Ascii => {
+            text = String::from_utf8(body)
+                .map_err(|_| LoaderError::FileError("PLY ASCII body isn't valid text".into()))?;
+            Body::Ascii(text.split_ascii_whitespace())
+        }
+        Format::BinaryLittleEndian => Body::Binary {
+            bytes: &body,
+            big_endian: false,
+        },
+        Format::BinaryBigEndian => Body::Binary {
+            bytes: &body,
+            big_endian: true,
+        },
+    };
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let mut has_faces = false;
+    let mut attributes = Attributes::default();
+
+    for element in header.elements.iter() {
+        match element.name.as_str() {
+            "vertex" => {
+                attributes = vertex_attributes(element);
+                // the count is only trusted as far as the body could hold that many vertices
+                vertices.reserve(element.count.min(body_len));
+                for _ in 0..element.count {
+                    vertices.push(read_vertex(element, &mut data)?);
+                }
+            }
+            "face" => {
+                has_faces |= element.count > 0;
+                for _ in 0..element.count {
+                    read_face(element, &mut data, &mut indices)?;
+                }
+            }
+            // edges, materials and whatever else a file might carry
+            _ => {
+                for _ in 0..element.count {
+                    for prop in element.properties.iter() {
+                        data.skip(prop)?;
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(&i) = indices.iter().find(|&&i| i as usize >= vertices.len()) {
+        return Err(LoaderError::FileError(format!(
+            "PLY face references vertex {} but there are only {}",
+            i,
+            vertices.len()
+        )));
+    }
+
+    let mode = if has_faces { gl::TRIANGLES } else { gl::POINTS };
+    let aabb = Aabb::from_points(vertices.iter().map(|v| v.pos));
+    let mut primitive = PrimitiveData {
+        vertices,
+        indices,
+        material: None,
+        aabb,
+        mode,
+        targets: Vec::new(),
+        attributes,
+        variants: Vec::new(),
+    };
+    // meshes without normals are flat shaded, point clouds don't need any
+    if has_faces && !primitive.attributes.contains(Attributes::NORMAL) {
+        primitive.generate_flat_normals();
+    }
+
+    Ok(SceneData {
+        nodes: vec![Node::with_mesh(0)],
+        scenes: vec![SceneRoots::new(None, vec![0])],
+        default_scene: 0,
+        meshes: vec![MeshData::new(vec![primitive], None)],
+        images: Vec::new(),
+        textures: Vec::new(),
+        materials: Vec::new(),
+        skins: Vec::new(),
+        animations: Vec::new(),
+        cameras: Vec::new(),
+        lights: Vec::new(),
+        variants: Vec::new(),
+    })
+}
+
+fn vertex_attributes(element: &Element) -> Attributes {
+    let mut attributes = Attributes::default();
+
+    for prop in element.properties.iter() {
+        match prop.name.as_str() {
+            "nx" => attributes.insert(Attributes::NORMAL),
+            "s" | "u" | "texture_u" => attributes.insert(Attributes::TEX_COORD_0),
+            "red" | "r" => attributes.insert(Attributes::COLOR),
+            _ => {}
+        }
+    }
+
+    attributes
+}
+
+fn read_vertex(element: &Element, data: &mut Body) -> Result<Vertice, LoaderError> {
+    let mut vertice = Vertice::default();
+    let mut normal = Vector3::new(0.0, 0.0, 0.0);
+    let mut tex = Vector2::new(0.0, 0.0);
+    let mut color = Vector4::new(1.0, 1.0, 1.0, 1.0);
+
+    for prop in element.properties.iter() {
+        let ty = match prop.kind {
+            PropertyKind::Scalar(ty) => ty,
+            PropertyKind::List(..) => {
+                data.skip(prop)?;
+                continue;
+            }
+        };
+        let value = data.read(ty)?;
+
+        // integer colors go from 0 to the type max, float colors from 0 to 1
+        let channel = || match ty {
+            Scalar::U8 => (value / 255.0) as f32,
+            Scalar::U16 => (value / 65535.0) as f32,
+            _ => value as f32,
+        };
+
+        match prop.name.as_str() {
+            "x" => vertice.pos.x = value as f32,
+            "y" => vertice.pos.y = value as f32,
+            "z" => vertice.pos.z = value as f32,
+            "nx" => normal.x = value as f32,
+            "ny" => normal.y = value as f32,
+            "nz" => normal.z = value as f32,
+            "s" | "u" | "texture_u" => tex.x = value as f32,
+            "t" | "v" | "texture_v" => tex.y = value as f32,
+            "red" | "r" => color.x = channel(),
+            "green" | "g" => color.y = channel(),
+            "blue" | "b" => color.z = channel(),
+            "alpha" | "a" => color.w = channel(),
+            _ => {}
+        }
+    }
+
+    vertice.normal = normal;
+    vertice.tex = tex;
+    vertice.color = color;
+
+    Ok(vertice)
+}
+
+fn read_face(
+    element: &Element,
+    data: &mut Body,
+    indices: &mut Vec<u32>,
+) -> Result<(), LoaderError> {
+    for prop in element.properties.iter() {
+        match prop.kind {
+            PropertyKind::List(count_ty, item_ty)
+                if prop.name == "vertex_indices" || prop.name == "vertex_index" =>
+            {
+                let count = data.read(count_ty)? as usize;
+                let polygon = (0..count)
+                    .map(|_| data.read(item_ty).map(|i| i as u32))
+                    .collect::<Result<Vec<u32>, LoaderError>>()?;
+
+                // triangulate as a fan, fine for the convex polygons scanners output
+                for i in 1..polygon.len().saturating_sub(1) {
+                    indices.extend_from_slice(&[polygon[0], polygon[i], polygon[i + 1]]);
+                }
+            }
+            _ => data.skip(prop)?,
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum PropertyKind {
+    Scalar(Scalar),
+    List(Scalar, Scalar), // (count type, item type)
+}
+
+#[derive(Debug, Clone)]
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+#[derive(Debug, Clone)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+#[derive(Debug, Clone)]
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+}
+
+impl Header {
+    fn parse<R: BufRead>(reader: &mut R) -> Result<Self, LoaderError> {
+        let malformed =
+            |msg: &str| LoaderError::FileError(format!("malformed PLY header: {}", msg));
+
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if line.trim_end() != "ply" {
+            return Err(malformed("missing the ply magic number"));
+        }
+
+        let mut format = None;
+        let mut elements: Vec<Element> = Vec::new();
+
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(malformed("missing end_header"));
+            }
+
+            let mut words = line.split_ascii_whitespace();
+
+            match words.next() {
+                Some("format") => {
+                    format = Some(match (words.next(), words.next()) {
+                        (Some("ascii"), Some("1.0")) => Format::Ascii,
+                        (Some("binary_little_endian"), Some("1.0")) => Format::BinaryLittleEndian,
+                        (Some("binary_big_endian"), Some("1.0")) => Format::BinaryBigEndian,
+                        _ => return Err(malformed(line.trim_end())),
+                    });
+                }
+                Some("element") => {
+                    let name = words.next().ok_or_else(|| malformed(line.trim_end()))?;
+                    let count = words
+                        .next()
+                        .and_then(|c| c.parse().ok())
+                        .ok_or_else(|| malformed(line.trim_end()))?;
+
+                    elements.push(Element {
+                        name: name.to_owned(),
+                        count,
+                        properties: Vec::new(),
+                    });
+                }
+                Some("property") => {
+                    let element = elements
+                        .last_mut()
+                        .ok_or_else(|| malformed("property declared before any element"))?;
+
+                    let kind = match words.next() {
+                        Some("list") => {
+                            let count = words.next().and_then(Scalar::parse);
+                            let item = words.next().and_then(Scalar::parse);
+
+                            match (count, item) {
+                                (Some(c), Some(i)) => PropertyKind::List(c, i),
+                                _ => return Err(malformed(line.trim_end())),
+                            }
+                        }
+                        Some(ty) => PropertyKind::Scalar(
+                            Scalar::parse(ty).ok_or_else(|| malformed(line.trim_end()))?,
+                        ),
+                        None => return Err(malformed(line.trim_end())),
+                    };
+                    let name = words.next().ok_or_else(|| malformed(line.trim_end()))?;
+
+                    element.properties.push(Property {
+                        name: name.to_owned(),
+                        kind,
+                    });
+                }
+                Some("end_header") => break,
+                Some("comment") | Some("obj_info") | None => {}
+                Some(_) => return Err(malformed(line.trim_end())),
+            }
+        }
+
+        let format = format.ok_or_else(|| malformed("missing format"))?;
+
+        Ok(Self { format, elements })
+    }
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl<'a> Body<'a> {
+    // every PLY scalar fits in a f64 without losing precision
+    fn read(&mut self, ty: Scalar) -> Result<f64, LoaderError> {
+        let truncated = || LoaderError::FileError("PLY body ended before the header said".into());
+
+        match self {
+            Body::Ascii(words) => {
+                let word = words.next().ok_or_else(truncated)?;
+
+                word.parse()
+                    .map_err(|_| LoaderError::FileError(format!("invalid PLY value: {}", word)))
+            }
+            Body::Binary { bytes, big_endian } => {
+                if bytes.len() < ty.size() {
+                    return Err(truncated());
+                }
+                let (value, rest) = bytes.split_at(ty.size());
+                *bytes = rest;
+
+                macro_rules! from_bytes {
+                    ($t:ty) => {{
+                        let mut arr = [0; std::mem::size_of::<$t>()];
+                        arr.copy_from_slice(value);
+                        if *big_endian {
+                            <$t>::from_be_bytes(arr) as f64
+                        } else {
+                            <$t>::from_le_bytes(arr) as f64
+                        }
+                    }};
+                }
+
+                Ok(match ty {
+                    Scalar::I8 => from_bytes!(i8),
+                    Scalar::U8 => from_bytes!(u8),
+                    Scalar::I16 => from_bytes!(i16),
+                    Scalar::U16 => from_bytes!(u16),
+                    Scalar::I32 => from_bytes!(i32),
+                    Scalar::U32 => from_bytes!(u32),
+                    Scalar::F32 => from_bytes!(f32),
+                    Scalar::F64 => from_bytes!(f64),
+                })
+            }
+        }
+    }
+
+    fn skip(&mut self, prop: &Property) -> Result<(), LoaderError> {
+        match prop.kind {
+            PropertyKind::Scalar(ty) => {
+                self.read(ty)?;
+            }
+            PropertyKind::List(count_ty, item_ty) => {
+                let count = self.read(count_ty)? as usize;
+                for _ in 0..count {
+                    self.read(item_ty)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn ascii_mesh() {
+        let src = "ply\n\
+                   format ascii 1.0\n\
+                   comment a single quad\n\
+                   element vertex 4\n\
+                   property float x\n\
+                   property float y\n\
+                   property float z\n\
+                   element face 1\n\
+                   property list uchar int vertex_indices\n\
+                   end_header\n\
+                   0 0 0\n1 0 0\n1 1 0\n0 1 0\n\
+                   4 0 1 2 3\n";
+
+        let data = parse_ply(Cursor::new(src)).unwrap();
+        let prim = &data.meshes[0].primitives[0];
+
+        assert_eq!(prim.mode, gl::TRIANGLES);
+        assert_eq!(prim.aabb.max, Vector3::new(1.0, 1.0, 0.0));
+
+        // the quad is split into two triangles with flat normals
+        assert!(prim.indices.is_empty());
+        assert_eq!(prim.vertices.len(), 6);
+        assert_eq!(prim.vertices[5].pos, Vector3::new(0.0, 1.0, 0.0));
+        assert!(prim.attributes.contains(Attributes::NORMAL));
+        for v in prim.vertices.iter() {
+            assert_eq!(v.normal, Vector3::new(0.0, 0.0, 1.0));
+        }
+    }
+
+    fn colored_cloud(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian {
+            "binary_big_endian"
+        } else {
+            "binary_little_endian"
+        };
+        let mut src = format!(
+            "ply\nformat {} 1.0\nelement vertex 2\n\
+             property float x\nproperty float y\nproperty float z\n\
+             property uchar red\nproperty uchar green\nproperty uchar blue\n\
+             end_header\n",
+            format
+        )
+        .into_bytes();
+
+        for (pos, col) in [
+            ([1.0_f32, 2.0, 3.0], [255_u8, 0, 51]),
+            ([-1.0, 0.5, 0.0], [0, 255, 0]),
+        ]
+        .iter()
+        {
+            for p in pos.iter() {
+                if big_endian {
+                    src.extend_from_slice(&p.to_be_bytes());
+                } else {
+                    src.extend_from_slice(&p.to_le_bytes());
+                }
+            }
+            src.extend_from_slice(col);
+        }
+
+        src
+    }
+
+    #[test]
+    fn binary_point_cloud() {
+        for &big_endian in [false, true].iter() {
+            let data = parse_ply(Cursor::new(colored_cloud(big_endian))).unwrap();
+            let prim = &data.meshes[0].primitives[0];
+
+            assert_eq!(prim.mode, gl::POINTS);
+            assert!(prim.indices.is_empty());
+            assert_eq!(prim.vertices[0].pos, Vector3::new(1.0, 2.0, 3.0));
+            assert_eq!(prim.vertices[0].color, Vector4::new(1.0, 0.0, 0.2, 1.0));
+            assert_eq!(prim.vertices[1].pos, Vector3::new(-1.0, 0.5, 0.0));
+            assert_eq!(prim.vertices[1].color, Vector4::new(0.0, 1.0, 0.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn malformed_header() {
+        let bad = [
+            "plx\nformat ascii 1.0\nend_header\n",
+            "ply\nformat ascii 2.0\nend_header\n",
+            "ply\nproperty float x\nend_header\n",
+            "ply\nformat ascii 1.0\nelement vertex many\nend_header\n",
+            "ply\nformat ascii 1.0\nelement vertex 1\nproperty quad x\nend_header\n",
+            "ply\nformat ascii 1.0\nelement vertex 1\n",
+        ];
+
+        for src in bad.iter() {
+            match parse_ply(Cursor::new(*src)) {
+                Err(LoaderError::FileError(_)) => {}
+                other => panic!("expected a FileError, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_body() {
+        let src = "ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nend_header\n1.0\n";
+
+        assert!(matches!(
+            parse_ply(Cursor::new(src)),
+            Err(LoaderError::FileError(_))
+        ));
+
+        // a huge count is an error, not an allocation failure
+        let src = "ply\nformat binary_little_endian 1.0\nelement vertex 99999999999999\n\
+                   property float x\nend_header\n\0\0\0\0";
+        assert!(matches!(
+            parse_ply(Cursor::new(src)),
+            Err(LoaderError::FileError(_))
+        ));
+    }
+}
@@ -0,0 +1,382 @@
+use cgmath::{prelude::*, Quaternion, Vector3};
+
+use super::{animations::Interpolate, layer::Mask, Node};
+
+/// Values of the node properties an animation drives at one point in time.
+///
+/// Each entry is a `(node, value)` pair, nodes missing from a list keep their current value.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pose {
+    pub translations: Vec<(usize, Vector3<f32>)>,
+    pub rotations: Vec<(usize, Quaternion<f32>)>,
+    pub scales: Vec<(usize, Vector3<f32>)>,
+    pub weights: Vec<(usize, Vec<f32>)>, // morph target weights
+}
+
+impl Pose {
+    /// Blends towards `other`, `t` is the weight of `other`.
+    pub fn blend(&self, other: &Pose, t: f32) -> Pose {
+        Pose::mix(&[(self, 1.0 - t), (other, t)])
+    }
+
+    /// Weighted mix of several poses, rotations are slerped.
+    ///
+    /// Each property is mixed among the poses that drive it with their weights normalized, so a
+    /// property only one pose has keeps its value whatever the weight. Poses without weight
+    /// are left out.
+    pub fn mix(poses: &[(&Pose, f32)]) -> Pose {
+        Pose {
+            translations: mix(poses.iter().map(|&(p, w)| (&p.translations[..], w))),
+            rotations: mix(poses.iter().map(|&(p, w)| (&p.rotations[..], w))),
+            scales: mix(poses.iter().map(|&(p, w)| (&p.scales[..], w))),
+            weights: mix(poses.iter().map(|&(p, w)| (&p.weights[..], w))),
+        }
+    }
+
+    /// The part of the pose driving nodes in `mask`.
+    pub fn masked(&self, mask: &Mask) -> Pose {
+        fn keep<T: Clone>(list: &[(usize, T)], mask: &Mask) -> Vec<(usize, T)> {
+            list.iter()
+                .filter(|(node, _)| mask.contains(*node))
+                .cloned()
+                .collect()
+        }
+
+        Pose {
+            translations: keep(&self.translations, mask),
+            rotations: keep(&self.rotations, mask),
+            scales: keep(&self.scales, mask),
+            weights: keep(&self.weights, mask),
+        }
+    }
+
+    /// Every property of every node as it is now, sorted by node. Kept as the rest pose layers
+    /// start from, see `Animations::rest`.
+    pub fn rest(nodes: &[Node]) -> Pose {
+        let mut pose = Pose::default();
+        for (index, node) in nodes.iter().enumerate() {
+            pose.set_rest(index, node);
+        }
+
+        pose
+    }
+
+    /// Sets the values of `node` in a rest pose to the ones of `values`, keeping it sorted.
+    pub fn set_rest(&mut self, node: usize, values: &Node) {
+        set(&mut self.translations, node, values.translation);
+        set(&mut self.rotations, node, values.rotation);
+        set(&mut self.scales, node, values.scale);
+        set(&mut self.weights, node, values.weights.clone());
+    }
+
+    /// Blends `top` over this pose by `weight`, properties this pose doesn't have start from
+    /// their value in the `rest` pose.
+    pub fn overlay(&self, top: &Pose, weight: f32, rest: &Pose) -> Pose {
+        Pose {
+            translations: overlay(
+                &self.translations,
+                &top.translations,
+                weight,
+                &rest.translations,
+            ),
+            rotations: overlay(&self.rotations, &top.rotations, weight, &rest.rotations),
+            scales: overlay(&self.scales, &top.scales, weight, &rest.scales),
+            weights: overlay(&self.weights, &top.weights, weight, &rest.weights),
+        }
+    }
+
+    /// What has to be added to `reference` to get this pose, for the properties both have.
+    ///
+    /// Rotations are relative to the reference and scales are ratios.
+    pub fn difference(&self, reference: &Pose) -> Pose {
+        Pose {
+            translations: difference(&self.translations, &reference.translations, |v, r| v - r),
+            rotations: difference(&self.rotations, &reference.rotations, |v, r| {
+                r.conjugate() * v
+            }),
+            scales: difference(&self.scales, &reference.scales, |v, r| {
+                v.div_element_wise(*r)
+            }),
+            weights: difference(&self.weights, &reference.weights, |v, r| {
+                v.iter().zip(r.iter()).map(|(v, r)| v - r).collect()
+            }),
+        }
+    }
+
+    /// Adds a `difference` scaled by `weight`, properties this pose doesn't have start from
+    /// their value in the `rest` pose.
+    pub fn add(&self, delta: &Pose, weight: f32, rest: &Pose) -> Pose {
+        let one = Vector3::new(1.0, 1.0, 1.0);
+
+        Pose {
+            translations: add(
+                &self.translations,
+                &delta.translations,
+                &rest.translations,
+                |v, d| v + d * weight,
+            ),
+            rotations: add(
+                &self.rotations,
+                &delta.rotations,
+                &rest.rotations,
+                |v, d| v * Quaternion::one().linear(*d, weight),
+            ),
+            scales: add(&self.scales, &delta.scales, &rest.scales, |v, d| {
+                v.mul_element_wise(one.lerp(*d, weight))
+            }),
+            weights: add(&self.weights, &delta.weights, &rest.weights, |v, d| {
+                v.iter()
+                    .zip(d.iter())
+                    .map(|(v, d)| v + d * weight)
+                    .collect()
+            }),
+        }
+    }
+
+    /// Follows the nodes to their new indices after a removal, see `Graph::remove`.
+    pub(super) fn retarget(&mut self, map: &[Option<usize>]) {
+        fn follow<T>(list: &mut Vec<(usize, T)>, map: &[Option<usize>]) {
+            *list = list
+                .drain(..)
+                .filter_map(|(node, v)| map[node].map(|node| (node, v)))
+                .collect();
+        }
+
+        follow(&mut self.translations, map);
+        follow(&mut self.rotations, map);
+        follow(&mut self.scales, map);
+        follow(&mut self.weights, map);
+    }
+
+    /// Writes the pose into the nodes it targets.
+    pub fn apply(&self, nodes: &mut [Node]) {
+        for &(node, translation) in self.translations.iter() {
+            nodes[node].translation = translation;
+        }
+        for &(node, rotation) in self.rotations.iter() {
+            nodes[node].rotation = rotation;
+        }
+        for &(node, scale) in self.scales.iter() {
+            nodes[node].scale = scale;
+        }
+        for (node, weights) in self.weights.iter() {
+            nodes[*node].weights.clone_from(weights);
+        }
+
+        let moved = self
+            .translations
+            .iter()
+            .map(|&(node, _)| node)
+            .chain(self.rotations.iter().map(|&(node, _)| node))
+            .chain(self.scales.iter().map(|&(node, _)| node));
+
+        for node in moved {
+            nodes[node].update();
+        }
+    }
+}
+
+fn mix<'a, T, I>(lists: I) -> Vec<(usize, T)>
+where
+    T: Interpolate + Clone + 'a,
+    I: Iterator<Item = (&'a [(usize, T)], f32)>,
+{
+    // (node, value, weight so far), each new value is blended in by its share of the weight
+    let mut mixed: Vec<(usize, T, f32)> = Vec::new();
+
+    for (list, weight) in lists.filter(|&(_, weight)| weight > 0.0) {
+        for (node, value) in list.iter() {
+            match mixed.iter_mut().find(|(n, ..)| n == node) {
+                Some((_, mixed_value, total)) => {
+                    *total += weight;
+                    *mixed_value = mixed_value.linear(value.clone(), weight / *total);
+                }
+                None => mixed.push((*node, value.clone(), weight)),
+            }
+        }
+    }
+
+    mixed
+        .into_iter()
+        .map(|(node, value, _)| (node, value))
+        .collect()
+}
+
+fn value<T>(list: &[(usize, T)], node: usize) -> Option<&T> {
+    list.iter()
+        .find(|(n, _)| *n == node)
+        .map(|(_, value)| value)
+}
+
+// rest poses are sorted by node, see `Pose::rest`
+fn rest_value<T>(rest: &[(usize, T)], node: usize) -> Option<&T> {
+    rest.binary_search_by_key(&node, |&(n, _)| n)
+        .ok()
+        .map(|i| &rest[i].1)
+}
+
+fn set<T>(rest: &mut Vec<(usize, T)>, node: usize, value: T) {
+    match rest.binary_search_by_key(&node, |&(n, _)| n) {
+        Ok(i) => rest[i].1 = value,
+        Err(i) => rest.insert(i, (node, value)),
+    }
+}
+
+fn overlay<T>(
+    base: &[(usize, T)],
+    top: &[(usize, T)],
+    weight: f32,
+    rest: &[(usize, T)],
+) -> Vec<(usize, T)>
+where
+    T: Interpolate + Clone,
+{
+    let mut overlaid = base.to_vec();
+
+    for (node, value) in top.iter() {
+        match overlaid.iter_mut().find(|(n, _)| n == node) {
+            Some((_, below)) => *below = below.linear(value.clone(), weight),
+            None => {
+                // nodes added without a rest pose take the layer as is
+                let start = rest_value(rest, *node).unwrap_or(value);
+                overlaid.push((*node, start.linear(value.clone(), weight)));
+            }
+        }
+    }
+
+    overlaid
+}
+
+fn difference<T, F>(list: &[(usize, T)], reference: &[(usize, T)], diff: F) -> Vec<(usize, T)>
+where
+    F: Fn(&T, &T) -> T,
+{
+    list.iter()
+        .filter_map(|(node, v)| value(reference, *node).map(|r| (*node, diff(v, r))))
+        .collect()
+}
+
+fn add<T, F>(
+    list: &[(usize, T)],
+    delta: &[(usize, T)],
+    rest: &[(usize, T)],
+    add: F,
+) -> Vec<(usize, T)>
+where
+    T: Clone,
+    F: Fn(&T, &T) -> T,
+{
+    let mut added: Vec<(usize, T)> = list
+        .iter()
+        .map(|(node, v)| match value(delta, *node) {
+            Some(d) => (*node, add(v, d)),
+            None => (*node, v.clone()),
+        })
+        .collect();
+
+    for (node, d) in delta.iter() {
+        if value(list, *node).is_none() {
+            if let Some(v) = rest_value(rest, *node) {
+                added.push((*node, add(v, d)));
+            }
+        }
+    }
+
+    added
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{vec3, Deg};
+
+    #[test]
+    fn weighted_mix() {
+        let walk = Pose {
+            translations: vec![(0, vec3(0.0, 0.0, 0.0)), (1, vec3(1.0, 0.0, 0.0))],
+            rotations: vec![(0, Quaternion::from_angle_y(Deg(0.0)))],
+            ..Pose::default()
+        };
+        let run = Pose {
+            translations: vec![(0, vec3(4.0, 0.0, 0.0))],
+            rotations: vec![(0, Quaternion::from_angle_y(Deg(90.0)))],
+            ..Pose::default()
+        };
+
+        let half = walk.blend(&run, 0.5);
+        assert_eq!(half.translations[0], (0, vec3(2.0, 0.0, 0.0)));
+        // only walk drives node 1
+        assert_eq!(half.translations[1], (1, vec3(1.0, 0.0, 0.0)));
+
+        let expected = Quaternion::from_angle_y(Deg(45.0));
+        assert!((half.rotations[0].1 - expected).magnitude() < 1e-5);
+
+        // weights are normalized, zero weights are left out
+        let mixed = Pose::mix(&[(&walk, 1.0), (&run, 3.0)]);
+        assert_eq!(mixed.translations[0], (0, vec3(3.0, 0.0, 0.0)));
+        assert_eq!(walk.blend(&run, 1.0).translations.len(), 1);
+    }
+
+    #[test]
+    fn layers() {
+        let base = Pose {
+            translations: vec![(0, vec3(1.0, 0.0, 0.0))],
+            rotations: vec![(1, Quaternion::one())],
+            scales: vec![(1, vec3(2.0, 2.0, 2.0))],
+            ..Pose::default()
+        };
+        let reference = Pose {
+            rotations: vec![(1, Quaternion::from_angle_z(Deg(10.0)))],
+            scales: vec![(1, vec3(1.0, 1.0, 1.0))],
+            ..Pose::default()
+        };
+        let wave = Pose {
+            translations: vec![(0, vec3(5.0, 0.0, 0.0))],
+            rotations: vec![(1, Quaternion::from_angle_z(Deg(50.0)))],
+            scales: vec![(1, vec3(2.0, 1.0, 1.0))],
+            ..Pose::default()
+        };
+
+        let mut nodes = vec![Node::with_mesh(0), Node::with_mesh(0), Node::with_mesh(0)];
+        nodes[1].translation = vec3(0.0, 2.0, 0.0);
+        nodes[2].rotation = Quaternion::from_angle_z(Deg(-40.0));
+        let rest = Pose::rest(&nodes);
+
+        // additive: 40 degrees more at full weight, 20 at half
+        let added = base.add(&wave.difference(&reference), 0.5, &rest);
+        let expected = Quaternion::from_angle_z(Deg(20.0));
+        assert!((added.rotations[0].1 - expected).magnitude() < 1e-5);
+        assert!((added.scales[0].1 - vec3(3.0, 2.0, 2.0)).magnitude() < 1e-5);
+        // the reference doesn't have it, neither does the difference
+        assert_eq!(added.translations, base.translations);
+
+        // what the base doesn't drive is added to the rest pose
+        let delta = Pose {
+            rotations: vec![(2, Quaternion::from_angle_z(Deg(40.0)))],
+            ..Pose::default()
+        };
+        let added = base.add(&delta, 1.0, &rest);
+        assert_eq!(added.rotations[1].0, 2);
+        assert!((added.rotations[1].1 - Quaternion::one()).magnitude() < 1e-5);
+
+        // removing node 0 shifts the others down
+        let mut moved = delta.clone();
+        moved.retarget(&[None, Some(0), Some(1)]);
+        assert_eq!(moved.rotations[0].0, 1);
+
+        // masks keep the listed nodes only
+        let masked = wave.masked(&Mask::Nodes(vec![1]));
+        assert!(masked.translations.is_empty());
+        assert_eq!(masked.rotations.len(), 1);
+
+        let top = Pose {
+            translations: vec![(0, vec3(3.0, 0.0, 0.0)), (1, vec3(0.0, 4.0, 0.0))],
+            ..Pose::default()
+        };
+        let overlaid = base.overlay(&top, 0.5, &rest);
+        assert_eq!(
+            overlaid.translations,
+            vec![(0, vec3(2.0, 0.0, 0.0)), (1, vec3(0.0, 3.0, 0.0))]
+        );
+    }
+}
@@ -1,31 +1,52 @@
 use crate::{
     aabb::Aabb,
+    core::camera::Camera,
     ogl::{buffers::*, material::Material, texture::Texture2D},
     ImRender,
 };
 use cgmath::{prelude::*, Matrix4, Quaternion, Vector3};
-use thiserror::Error;
 
 use super::{
-    animations::{Animation, Animations, Mode},
+    animations::{Animations, Mode},
+    data::{SceneData, SceneRoots, TextureData},
+    graph::{Graph, GraphError},
+    instance_layout,
+    layer::{Blend, Layer},
+    loader::LoaderError,
+    node::{self, Descendants},
+    playback::Wrap,
     skin::Skin,
-    Mesh, Node, Primitive, Vertice,
+    Light, Mesh, MeshData, Node, Pose, StateMachine,
 };
 
 // use rayon::prelude::*;
-use std::path::Path;
+use std::{path::Path, rc::Rc};
 
 #[derive(Debug)]
 pub struct Scene {
     // use boxed slices instead?
-    pub nodes: Vec<Node>,  // all nodes
-    pub roots: Vec<usize>, // indices of the roots
+    pub nodes: Vec<Node>,                // all nodes
+    instancing: Vec<Option<Instancing>>, // instance transforms of each node
+    pub scenes: Vec<SceneRoots>,
+    active_scene: usize, // index of the scene being shown
 
     node_parent: Vec<(usize, Option<usize>)>, // (node, parent) indices for traversal
-    pub textures: Vec<Texture2D>,
-    pub materials: Vec<Material>,
+
+    // shared by every instance of the scene, see `Scene::instance`
+    pub meshes: Rc<[Mesh]>,
+    mesh_data: Rc<[MeshData]>, // CPU copies kept for exporting
+    images: Rc<[gltf::image::Data]>,
+    pub textures: Rc<[Texture2D]>,
+    texture_data: Rc<[TextureData]>, // image and sampler of each texture
+    pub materials: Rc<[Material]>,
+
     pub animations: Animations,
     pub skins: Vec<Skin>,
+    pub cameras: Vec<Camera>,
+    view_node: Option<usize>, // node of the camera being looked through
+    pub lights: Vec<Light>,
+    pub variants: Vec<String>,
+    active_variant: Option<usize>, // None shows the default materials
 
     pub aabb: Aabb,
     pub scale: f32,
@@ -44,7 +65,311 @@ pub struct Scene {
 
 impl Scene {
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, LoaderError> {
-        load_gltf(path)
+        SceneData::load(path).map(Scene::from)
+    }
+
+    /// Writes the scene, with its current node transforms, as a `.gltf` and `.bin` pair.
+    pub fn save_gltf<P: AsRef<Path>>(&self, path: P) -> Result<(), LoaderError> {
+        self.to_data().save_gltf(path)
+    }
+
+    /// Writes the scene, with its current node transforms, as a binary glTF.
+    pub fn save_glb<P: AsRef<Path>>(&self, path: P) -> Result<(), LoaderError> {
+        self.to_data().save_glb(path)
+    }
+
+    /// Copies the CPU side of the scene back into a `SceneData`.
+    ///
+    /// The viewer transform (`scale`, `rotation`, `translation`) isn't part of the data.
+    pub fn to_data(&self) -> SceneData {
+        SceneData {
+            nodes: self.nodes.clone(),
+            scenes: self.scenes.clone(),
+            default_scene: self.active_scene,
+            meshes: self.mesh_data.to_vec(),
+            images: self.images.to_vec(),
+            textures: self.texture_data.to_vec(),
+            materials: self.materials.to_vec(),
+            skins: self.skins.clone(),
+            animations: self.animations.inner.clone(),
+            cameras: self.cameras.clone(),
+            lights: self.lights.clone(),
+            variants: self.variants.clone(),
+        }
+    }
+
+    /// Another copy of the scene drawing with the same meshes, textures and materials.
+    ///
+    /// The copy starts out as this scene is now, but has its own nodes, animations and
+    /// variant, see `World::place`.
+    pub fn instance(&self) -> Self {
+        let mut scene = Scene {
+            nodes: self.nodes.clone(),
+            instancing: self
+                .nodes
+                .iter()
+                .map(|node| instancing(node, &self.meshes))
+                .collect(),
+            scenes: self.scenes.clone(),
+            active_scene: self.active_scene,
+            node_parent: Vec::new(),
+            meshes: Rc::clone(&self.meshes),
+            mesh_data: Rc::clone(&self.mesh_data),
+            images: Rc::clone(&self.images),
+            textures: Rc::clone(&self.textures),
+            texture_data: Rc::clone(&self.texture_data),
+            materials: Rc::clone(&self.materials),
+            animations: self.animations.clone(),
+            skins: self.skins.clone(),
+            cameras: self.cameras.clone(),
+            view_node: self.view_node,
+            lights: self.lights.clone(),
+            variants: self.variants.clone(),
+            active_variant: self.active_variant,
+            aabb: Aabb::default(),
+            scale: self.scale,
+            rotation: self.rotation,
+            translation: self.translation,
+            vao_: VertexArray::default(),
+            vbo_: VertexBuffer::default(),
+            ibo_: IndexBuffer::default(),
+            draw_aabb: self.draw_aabb,
+        };
+
+        scene.setup_active_scene();
+        scene
+    }
+
+    /// Reads a state machine to drive the animations and starts playing, see
+    /// `StateMachine::load`.
+    pub fn load_state_machine<P: AsRef<Path>>(&mut self, path: P) -> Result<(), LoaderError> {
+        let machine = StateMachine::load(path, &self.animations.inner)?;
+        self.animations.machine = Some(machine);
+        self.animations.playback.playing = true;
+
+        Ok(())
+    }
+
+    /// Roots of the active scene.
+    #[inline]
+    pub fn roots(&self) -> &[usize] {
+        &self.scenes[self.active_scene].roots
+    }
+
+    #[inline]
+    pub fn active_scene(&self) -> usize {
+        self.active_scene
+    }
+
+    /// Switches to another scene of the file, rebuilding the traversal order and the AABB.
+    pub fn set_active_scene(&mut self, index: usize) {
+        assert!(index < self.scenes.len(), "scene index out of bounds");
+
+        if index != self.active_scene {
+            self.active_scene = index;
+            self.setup_active_scene();
+        }
+    }
+
+    /// Nodes of the active scene that hold a camera, in traversal order.
+    pub fn camera_nodes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.node_parent
+            .iter()
+            .map(|&(node, _)| node)
+            .filter(move |&node| self.nodes[node].camera.is_some())
+    }
+
+    /// Nodes of the active scene that hold a mesh, in traversal order.
+    pub fn mesh_nodes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.node_parent
+            .iter()
+            .map(|&(node, _)| node)
+            .filter(move |&node| self.nodes[node].mesh.is_some())
+    }
+
+    /// Nodes of the active scene that hold a skinned mesh, in traversal order.
+    pub fn skin_nodes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.node_parent
+            .iter()
+            .map(|&(node, _)| node)
+            .filter(move |&node| self.nodes[node].skin.is_some())
+    }
+
+    /// First node of the active scene called `name`, in traversal order.
+    pub fn find_node(&self, name: &str) -> Option<usize> {
+        self.node_parent
+            .iter()
+            .map(|&(node, _)| node)
+            .find(|&node| self.nodes[node].name.as_deref() == Some(name))
+    }
+
+    /// Follows a path of node names from the roots of the active scene, e.g. `"Car/Body/Wheel_FL"`.
+    pub fn find_path(&self, path: &str) -> Option<usize> {
+        node::find_path(&self.nodes, self.roots(), path)
+    }
+
+    /// Parent of `node`, `None` for roots.
+    pub fn parent(&self, node: usize) -> Option<usize> {
+        self.nodes.iter().position(|n| n.children.contains(&node))
+    }
+
+    /// Parents of `node` up to its root, closest first.
+    pub fn ancestors(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(self.parent(node), move |&node| self.parent(node))
+    }
+
+    /// Every node below `node`, depth first.
+    pub fn descendants(&self, node: usize) -> Descendants<'_> {
+        Descendants::new(&self.nodes, node)
+    }
+
+    /// Adds `node` under `parent`, or as a root of the active scene, and returns its index.
+    pub fn spawn_node(&mut self, node: Node, parent: Option<usize>) -> Result<usize, GraphError> {
+        let instanced = instancing(&node, &self.meshes);
+        let scene = self.active_scene;
+        let index = self.graph().spawn(node, parent, scene)?;
+        self.instancing.push(instanced);
+        self.animations.rest.set_rest(index, &self.nodes[index]);
+
+        self.setup_active_scene();
+        Ok(index)
+    }
+
+    /// Removes `node` and everything below it.
+    ///
+    /// The nodes after it shift down, the returned map holds the new index of every old node,
+    /// `None` for the removed ones. Skins that lose a joint are removed as well.
+    pub fn remove_subtree(&mut self, node: usize) -> Result<Vec<Option<usize>>, GraphError> {
+        let map = self.graph().remove(node)?;
+
+        let mut index = 0;
+        self.instancing.retain(|_| {
+            index += 1;
+            map[index - 1].is_some()
+        });
+        self.view_node = self.view_node.and_then(|view| map[view]);
+        self.animations.rest.retarget(&map);
+        for layer in self.animations.layers.iter_mut() {
+            layer.mask.retarget(&map);
+            if let Blend::Additive(reference) = &mut layer.blend {
+                reference.retarget(&map);
+            }
+        }
+
+        self.setup_active_scene();
+        Ok(map)
+    }
+
+    /// Moves `node` under `parent`, or to the roots of the active scene.
+    ///
+    /// With `keep_world` the node stays where it is, otherwise it keeps its local transform.
+    /// Moving a node below itself is an error and leaves the scene untouched.
+    pub fn reparent(
+        &mut self,
+        node: usize,
+        parent: Option<usize>,
+        keep_world: bool,
+    ) -> Result<(), GraphError> {
+        let scene = self.active_scene;
+        self.graph().reparent(node, parent, scene, keep_world)?;
+        if keep_world {
+            self.animations.rest.set_rest(node, &self.nodes[node]);
+        }
+        self.setup_active_scene();
+
+        Ok(())
+    }
+
+    /// Copies `node` and everything below it under `parent`, or as a root of the active scene,
+    /// and returns the index of the copy.
+    pub fn clone_subtree(
+        &mut self,
+        node: usize,
+        parent: Option<usize>,
+    ) -> Result<usize, GraphError> {
+        let first = self.nodes.len();
+        let scene = self.active_scene;
+        let copy = self.graph().clone_subtree(node, parent, scene)?;
+
+        let meshes = &self.meshes;
+        let copies: Vec<_> = self.nodes[first..]
+            .iter()
+            .map(|node| instancing(node, meshes))
+            .collect();
+        self.instancing.extend(copies);
+        for (index, node) in self.nodes.iter().enumerate().skip(first) {
+            self.animations.rest.set_rest(index, node);
+        }
+
+        self.setup_active_scene();
+        Ok(copy)
+    }
+
+    fn graph(&mut self) -> Graph<'_> {
+        Graph {
+            nodes: &mut self.nodes,
+            scenes: &mut self.scenes,
+            skins: &mut self.skins,
+            animations: &mut self.animations.inner,
+        }
+    }
+
+    /// VAO drawing `primitive` of the mesh of `node` with its instance transforms, if it's
+    /// instanced.
+    #[inline]
+    pub fn instanced_vao(&self, node: usize, primitive: usize) -> Option<&VertexArray> {
+        self.instancing[node]
+            .as_ref()
+            .and_then(|instancing| instancing.vaos.get(primitive))
+    }
+
+    /// The camera of `node` placed at its current global transform.
+    pub fn node_camera(&self, node: usize) -> Option<Camera> {
+        let node = &self.nodes[node];
+
+        node.camera
+            .map(|c| self.cameras[c].transformed(node.global_transform))
+    }
+
+    /// The imported camera being looked through, `None` means the viewer's own camera.
+    #[inline]
+    pub fn view_camera(&self) -> Option<Camera> {
+        self.view_node.and_then(|node| self.node_camera(node))
+    }
+
+    /// Looks through the camera of `node`, or stops doing so with `None`.
+    pub fn set_view_camera(&mut self, node: Option<usize>) {
+        if let Some(node) = node {
+            assert!(
+                self.nodes[node].camera.is_some(),
+                "node {} has no camera",
+                node
+            );
+        }
+
+        self.view_node = node;
+    }
+
+    /// Index of the material variant being shown.
+    #[inline]
+    pub fn active_variant(&self) -> Option<usize> {
+        self.active_variant
+    }
+
+    /// Switches every primitive to the material mapped to `variant`, primitives without a
+    /// mapping for it and `None` go back to their default material.
+    pub fn set_variant(&mut self, variant: Option<usize>) {
+        assert!(variant.is_none_or(|v| v < self.variants.len()));
+
+        self.active_variant = variant;
+    }
+
+    /// Lights of the active scene along with the global transform of their nodes.
+    pub fn node_lights(&self) -> impl Iterator<Item = (&Light, Matrix4<f32>)> + '_ {
+        self.node_parent.iter().filter_map(move |&(node, _)| {
+            let node = &self.nodes[node];
+            node.light.map(|l| (&self.lights[l], node.global_transform))
+        })
     }
 
     pub fn update(&mut self, time: f32) {
@@ -66,11 +391,22 @@ impl Scene {
         // }
     }
 
-    fn initial_setup(&mut self) {
+    fn setup_active_scene(&mut self) {
+        self.node_parent =
+            super::node::build_tree(&self.nodes, &self.scenes[self.active_scene].roots);
+
+        // the camera may belong to a node the new scene doesn't show
+        if let Some(view) = self.view_node {
+            if !self.node_parent.iter().any(|&(node, _)| node == view) {
+                self.view_node = None;
+            }
+        }
+
         // first generate AABB
+        let bounds: Vec<Aabb> = self.meshes.iter().map(|m| m.aabb).collect();
         let mut aabb = Aabb::default();
-        for &node in self.roots.iter() {
-            let root_aabb = self.nodes[node].gen_aabb(&self.nodes, Matrix4::identity());
+        for &node in self.roots() {
+            let root_aabb = self.nodes[node].gen_aabb(&self.nodes, &bounds, Matrix4::identity());
             aabb = aabb.surrounds(&root_aabb);
         }
 
@@ -103,169 +439,226 @@ impl Scene {
     }
 }
 
-#[derive(Debug, Error)]
-pub enum LoaderError {
-    #[error("model loader io error: {0}")]
-    IOError(#[from] std::io::Error),
-    #[error("error in model file: {0}")]
-    FileError(String),
-    #[error("gltf loader error: {0}")]
-    GltfError(#[from] gltf::Error),
+// the instance transforms of a node, with a VAO reading them for each primitive of its mesh
+#[derive(Debug)]
+struct Instancing {
+    _buffer: VertexBuffer, // only read through the VAOs, kept alive with them
+    vaos: Vec<VertexArray>,
 }
 
-pub fn load_gltf<P>(path: P) -> Result<Scene, LoaderError>
-where
-    P: AsRef<Path>,
-{
-    let (document, buffers, images) = gltf::import(path)?;
-
-    assert_eq!(buffers.len(), document.buffers().count());
-    assert_eq!(images.len(), document.images().count());
-
-    let textures: Vec<Texture2D> = images.into_iter().map(Texture2D::from).collect();
-
-    let materials: Vec<Material> = document
-        .materials()
-        .into_iter()
-        // .par_bridge()
-        .map(Material::from)
-        .collect();
+fn instancing(node: &Node, meshes: &[Mesh]) -> Option<Instancing> {
+    if node.instances.is_empty() {
+        return None;
+    }
 
-    let nodes: Vec<Node> = document
-        .nodes()
-        .map(|node| process_node(&buffers, &node))
+    let buffer = VertexBuffer::new(&node.instances);
+    let primitives = node
+        .mesh
+        .map_or(&[][..], |mesh| &meshes[mesh].primitives[..]);
+    let vaos = primitives
+        .iter()
+        .map(|prim| {
+            let vao = VertexArray::new();
+            vao.add_buffer(&prim.vbo, &prim.attributes.layout());
+            vao.add_buffer(&buffer, &instance_layout());
+            vao
+        })
         .collect();
 
-    let skins = document
-        .skins()
-        .map(|s| Skin::from_gltf(&s, &buffers))
-        .collect();
+    Some(Instancing {
+        _buffer: buffer,
+        vaos,
+    })
+}
 
-    // this likely won't work for files with multiple scenes
-    // but It's not a concern for now
-    let mut roots = Vec::new();
-    for scene in document.scenes() {
-        for node in scene.nodes() {
-            roots.push(node.index());
+/// Uploads the meshes and images to the GPU, this needs a current OpenGL context.
+impl From<SceneData> for Scene {
+    fn from(data: SceneData) -> Self {
+        let SceneData {
+            nodes,
+            mut scenes,
+            default_scene,
+            meshes,
+            images,
+            textures: texture_data,
+            materials,
+            skins,
+            animations,
+            cameras,
+            lights,
+            variants,
+        } = data;
+
+        let mut animations = Animations::new(animations);
+        animations.rest = Pose::rest(&nodes);
+        let mesh_data: Rc<[MeshData]> = meshes.into();
+        let meshes: Rc<[Mesh]> = mesh_data.iter().cloned().map(Mesh::from).collect();
+        let instancing = nodes.iter().map(|node| instancing(node, &meshes)).collect();
+        // one GPU texture per glTF texture, they're what the materials index
+        let textures = texture_data
+            .iter()
+            .map(|t| Texture2D::from_image(&images[t.image], &t.sampler))
+            .collect();
+
+        // keeps roots() valid for files without any scene
+        if scenes.is_empty() {
+            scenes.push(SceneRoots::default());
         }
+        let active_scene = default_scene.min(scenes.len() - 1);
+
+        let mut scene = Scene {
+            scenes,
+            active_scene,
+            nodes,
+            instancing,
+            meshes,
+            mesh_data,
+            images: images.into(),
+            textures,
+            texture_data: texture_data.into(),
+            materials: materials.into(),
+            skins,
+            cameras,
+            view_node: None,
+            lights,
+            variants,
+            active_variant: None,
+            animations,
+            scale: 1.0,
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            aabb: Aabb::default(),
+            vao_: VertexArray::default(),
+            vbo_: VertexBuffer::default(),
+            ibo_: IndexBuffer::default(),
+            draw_aabb: false,
+            node_parent: Vec::new(),
+            // anim_index: None,
+        };
+
+        scene.setup_active_scene();
+        scene
     }
+}
 
-    let node_parent = super::node::build_tree(&nodes, &roots);
-    let animations = document
-        .animations()
-        .map(|anim| Animation::new(&anim, &buffers))
-        .collect();
+impl ImRender for Scene {
+    fn render(&mut self, ui: &imgui::Ui) {
+        if imgui::CollapsingHeader::new(imgui::im_str!("Scene")).build(&ui) {
+            imgui::TreeNode::new(imgui::im_str!("m1"))
+                .label(imgui::im_str!("Model"))
+                .build(ui, || {
+                    if self.scenes.len() > 1 {
+                        let scene_label = |i: usize, scene: &SceneRoots| match scene.name {
+                            Some(ref name) => imgui::im_str!("{}", name),
+                            None => imgui::im_str!("Scene {}", i),
+                        };
+                        let preview =
+                            scene_label(self.active_scene, &self.scenes[self.active_scene]);
+                        let mut selected = self.active_scene;
 
-    let mut scene = Scene {
-        roots,
-        nodes,
-        textures,
-        materials,
-        skins,
-        animations: Animations::new(animations),
-        scale: 1.0,
-        rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
-        translation: Vector3::new(0.0, 0.0, 0.0),
-        aabb: Aabb::default(),
-        vao_: VertexArray::default(),
-        vbo_: VertexBuffer::default(),
-        ibo_: IndexBuffer::default(),
-        draw_aabb: false,
-        node_parent,
-        // anim_index: None,
-    };
-
-    scene.initial_setup();
-    Ok(scene)
-}
+                        if let Some(combo) = imgui::ComboBox::new(imgui::im_str!("Active scene"))
+                            .preview_value(&preview)
+                            .begin(ui)
+                        {
+                            for (i, scene) in self.scenes.iter().enumerate() {
+                                let is_selected = i == self.active_scene;
 
-fn process_node(buffers: &[gltf::buffer::Data], node: &gltf::Node) -> Node {
-    let mesh = node.mesh().map(|m| process_mesh(&buffers, &m));
-    let transform = node.transform();
-    let children = node.children().map(|child| child.index()).collect();
-    let skin = node.skin().map(|s| s.index());
+                                if imgui::Selectable::new(&scene_label(i, scene))
+                                    .selected(is_selected)
+                                    .build(ui)
+                                {
+                                    selected = i;
+                                }
 
-    Node::new(mesh, transform, children, skin)
-}
+                                if is_selected {
+                                    ui.set_item_default_focus();
+                                }
+                            }
 
-fn process_mesh(buffers: &[gltf::buffer::Data], m: &gltf::Mesh) -> Mesh {
-    let primitives = m
-        .primitives()
-        .map(|primitive| {
-            let mut positions = Vec::new();
+                            combo.end(ui);
+                        }
 
-            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                        self.set_active_scene(selected);
+                    }
 
-            if let Some(pos) = reader.read_positions() {
-                positions.extend(pos.map(|p| Vector3::from(p)));
-            }
+                    let camera_nodes: Vec<usize> = self.camera_nodes().collect();
+                    if !camera_nodes.is_empty() {
+                        let camera_label = |node: Option<usize>| match node {
+                            Some(node) => match self.nodes[node].name {
+                                Some(ref name) => imgui::im_str!("{}", name),
+                                None => imgui::im_str!("Camera (node {})", node),
+                            },
+                            None => imgui::im_str!("{}", "Free camera"),
+                        };
+                        let preview = camera_label(self.view_node);
+                        let mut selected = self.view_node;
 
-            //TODO? dynamic vertice types according to the data present?
-            let mut vertices: Vec<Vertice> = positions
-                .into_iter()
-                .map(|p| Vertice {
-                    pos: Vector3::from(p),
-                    ..Vertice::default()
-                })
-                .collect();
-
-            if let Some(norm) = reader.read_normals() {
-                for (i, normal) in norm.enumerate() {
-                    vertices[i].normal = normal.into();
-                }
-            }
+                        if let Some(combo) = imgui::ComboBox::new(imgui::im_str!("View"))
+                            .preview_value(&preview)
+                            .begin(ui)
+                        {
+                            let options =
+                                std::iter::once(None).chain(camera_nodes.into_iter().map(Some));
 
-            if let Some(tex) = reader.read_tex_coords(0) {
-                for (i, coord) in tex.into_f32().enumerate() {
-                    vertices[i].tex = coord.into();
-                }
-            }
+                            for node in options {
+                                let is_selected = node == self.view_node;
 
-            if let Some(joints) = reader.read_joints(0) {
-                for (i, j) in joints.into_u16().enumerate() {
-                    vertices[i].joints =
-                        cgmath::Vector4::new(j[0] as f32, j[1] as f32, j[2] as f32, j[3] as f32);
-                }
-            }
+                                if imgui::Selectable::new(&camera_label(node))
+                                    .selected(is_selected)
+                                    .build(ui)
+                                {
+                                    selected = node;
+                                }
 
-            if let Some(w) = reader.read_weights(0) {
-                for (i, w) in w.into_f32().enumerate() {
-                    vertices[i].weights = cgmath::Vector4::from(w);
-                }
-            }
+                                if is_selected {
+                                    ui.set_item_default_focus();
+                                }
+                            }
 
-            if let Some(t) = reader.read_tangents() {
-                for (i, t) in t.enumerate() {
-                    vertices[i].tangent = t.into();
-                }
-            }
+                            combo.end(ui);
+                        }
 
-            let indices = if let Some(ind) = reader.read_indices() {
-                ind.into_u32().collect()
-            } else {
-                vec![]
-            };
-            let bounds = primitive.bounding_box().into();
-
-            Primitive::setup(
-                vertices,
-                indices,
-                primitive.material().index(),
-                bounds,
-                primitive.mode().as_gl_enum(),
-            )
-        })
-        .collect();
+                        self.view_node = selected;
+                    }
 
-    Mesh::new(primitives, m.name().map(String::from))
-}
+                    if !self.variants.is_empty() {
+                        let variant_label = |variant: Option<usize>| match variant {
+                            Some(v) => imgui::im_str!("{}", self.variants[v]),
+                            None => imgui::im_str!("{}", "Default"),
+                        };
+                        let preview = variant_label(self.active_variant);
+                        let mut selected = self.active_variant;
+
+                        if let Some(combo) = imgui::ComboBox::new(imgui::im_str!("Variant"))
+                            .preview_value(&preview)
+                            .begin(ui)
+                        {
+                            let options =
+                                std::iter::once(None).chain((0..self.variants.len()).map(Some));
+
+                            for variant in options {
+                                let is_selected = variant == self.active_variant;
+
+                                if imgui::Selectable::new(&variant_label(variant))
+                                    .selected(is_selected)
+                                    .build(ui)
+                                {
+                                    selected = variant;
+                                }
+
+                                if is_selected {
+                                    ui.set_item_default_focus();
+                                }
+                            }
+
+                            combo.end(ui);
+                        }
+
+                        if selected != self.active_variant {
+                            self.set_variant(selected);
+                        }
+                    }
 
-impl ImRender for Scene {
-    fn render(&mut self, ui: &imgui::Ui) {
-        if imgui::CollapsingHeader::new(imgui::im_str!("Scene")).build(&ui) {
-            imgui::TreeNode::new(imgui::im_str!("m1"))
-                .label(imgui::im_str!("Model"))
-                .build(ui, || {
                     if let Some(t_node) = imgui::TreeNode::new(imgui::im_str!("m1.1"))
                         .label(imgui::im_str!("Transformations"))
                         .push(ui)
@@ -319,24 +712,26 @@ impl ImRender for Scene {
                         .label(imgui::im_str!("Animations"))
                         .push(ui)
                     {
-                        let preview = match self.animations.mode {
+                        let preview = match self.animations.mode() {
                             Mode::All => imgui::im_str!("{}", "All"),
                             Mode::None => imgui::im_str!("{}", "None"),
                             Mode::Single(i) => imgui::im_str!("{}", self.animations.inner[i].name),
                         };
 
+                        let mut selected = self.animations.mode();
+
                         if let Some(combo) = imgui::ComboBox::new(imgui::im_str!("Selected"))
                             .preview_value(&preview)
                             .begin(ui)
                         {
                             for (i, anim) in self.animations.inner.iter().enumerate() {
-                                let is_selected = self.animations.mode == i;
+                                let is_selected = self.animations.mode() == i;
 
                                 if imgui::Selectable::new(&imgui::im_str!("{}", &anim.name))
                                     .selected(is_selected)
                                     .build(ui)
                                 {
-                                    self.animations.mode = Mode::Single(i);
+                                    selected = Mode::Single(i);
                                 }
 
                                 if is_selected {
@@ -344,26 +739,26 @@ impl ImRender for Scene {
                                 }
                             }
 
-                            let all = self.animations.mode == Mode::All;
+                            let all = self.animations.mode() == Mode::All;
 
                             if imgui::Selectable::new(imgui::im_str!("All"))
                                 .selected(all)
                                 .build(ui)
                             {
-                                self.animations.mode = Mode::All;
+                                selected = Mode::All;
                             }
 
                             if all {
                                 ui.set_item_default_focus();
                             }
 
-                            let none = self.animations.mode == Mode::None;
+                            let none = self.animations.mode() == Mode::None;
 
                             if imgui::Selectable::new(imgui::im_str!("None"))
                                 .selected(none)
                                 .build(ui)
                             {
-                                self.animations.mode = Mode::None;
+                                selected = Mode::None;
                             }
 
                             if none {
@@ -373,18 +768,136 @@ impl ImRender for Scene {
                             combo.end(ui);
                         }
 
-                        let b_label = if self.animations.paused {
-                            imgui::im_str!("Play")
-                        } else {
+                        if selected != self.animations.mode() {
+                            match selected {
+                                Mode::Single(clip) => {
+                                    let fade_time = self.animations.fade_time;
+                                    self.animations.cross_fade(clip, fade_time);
+                                }
+                                _ => self.animations.set_mode(selected),
+                            }
+                        }
+
+                        imgui::Slider::new(imgui::im_str!("Cross-fade"))
+                            .range(0.0..=2.0)
+                            .build(ui, &mut self.animations.fade_time);
+
+                        if self.animations.mode() == Mode::All {
+                            let animations = &mut self.animations;
+                            for (anim, weight) in
+                                animations.inner.iter().zip(animations.weights.iter_mut())
+                            {
+                                imgui::Slider::new(&imgui::im_str!("{}", anim.name))
+                                    .range(0.0..=1.0)
+                                    .build(ui, weight);
+                            }
+                        }
+
+                        let playback = &mut self.animations.playback;
+                        let b_label = if playback.playing {
                             imgui::im_str!("Pause")
+                        } else {
+                            imgui::im_str!("Play")
                         };
 
-                        self.animations.paused ^= ui.button(b_label, [40.0, 22.0]);
-                        // ui.same_line(0.0);
+                        playback.playing ^= ui.button(b_label, [40.0, 22.0]);
+                        ui.same_line(0.0);
 
-                        // if ui.button(imgui::im_str!("Reset"), [40.0, 22.0]) {
-                        //     self.animations.reset();
-                        // }
+                        if ui.button(imgui::im_str!("Reverse"), [60.0, 22.0]) {
+                            playback.reverse();
+                        }
+                        ui.same_line(0.0);
+
+                        if ui.button(imgui::im_str!("Reset"), [40.0, 22.0]) {
+                            self.animations.reset();
+                        }
+
+                        let playback = &mut self.animations.playback;
+                        imgui::Slider::new(imgui::im_str!("Speed"))
+                            .range(-2.0..=2.0)
+                            .build(ui, &mut playback.speed);
+
+                        let preview = imgui::im_str!("{}", playback.wrap.name());
+                        if let Some(combo) = imgui::ComboBox::new(imgui::im_str!("Wrap"))
+                            .preview_value(&preview)
+                            .begin(ui)
+                        {
+                            for &wrap in Wrap::ALL.iter() {
+                                if imgui::Selectable::new(&imgui::im_str!("{}", wrap.name()))
+                                    .selected(playback.wrap == wrap)
+                                    .build(ui)
+                                {
+                                    playback.wrap = wrap;
+                                }
+                            }
+
+                            combo.end(ui);
+                        }
+
+                        let mut time = playback.time();
+                        if imgui::Slider::new(imgui::im_str!("Time"))
+                            .range(0.0..=playback.duration())
+                            .build(ui, &mut time)
+                        {
+                            self.animations.seek(time);
+                        }
+
+                        ui.separator();
+                        let animations = &mut self.animations;
+                        let mut removed = None;
+                        for (i, layer) in animations.layers.iter_mut().enumerate() {
+                            let id = ui.push_id(i as i32);
+
+                            let anim = &animations.inner[layer.clip];
+                            imgui::Slider::new(&imgui::im_str!("{}", anim.name))
+                                .range(0.0..=1.0)
+                                .build(ui, &mut layer.weight);
+
+                            let mut additive = layer.blend != Blend::Override;
+                            if ui.checkbox(imgui::im_str!("Additive"), &mut additive) {
+                                layer.blend = if additive {
+                                    Blend::Additive(anim.sample(0.0))
+                                } else {
+                                    Blend::Override
+                                };
+                            }
+                            ui.same_line(0.0);
+
+                            if ui.small_button(imgui::im_str!("Remove")) {
+                                removed = Some(i);
+                            }
+
+                            id.pop(ui);
+                        }
+
+                        if let Some(i) = removed {
+                            animations.layers.remove(i);
+                        }
+
+                        if let Some(combo) = imgui::ComboBox::new(imgui::im_str!("Add layer"))
+                            .preview_value(imgui::im_str!(""))
+                            .begin(ui)
+                        {
+                            for (i, anim) in animations.inner.iter().enumerate() {
+                                if imgui::Selectable::new(&imgui::im_str!("{}", anim.name))
+                                    .build(ui)
+                                {
+                                    animations.layers.push(Layer::new(i, anim));
+                                }
+                            }
+
+                            combo.end(ui);
+                        }
+
+                        if let Some(machine) = self.animations.machine.as_mut() {
+                            if let Some(m_node) = imgui::TreeNode::new(imgui::im_str!("m1.2.1"))
+                                .label(imgui::im_str!("State machine"))
+                                .push(ui)
+                            {
+                                machine.render(ui);
+                                m_node.pop(ui);
+                            }
+                        }
 
                         a_node.pop(ui);
                     }
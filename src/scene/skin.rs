@@ -1,4 +1,5 @@
-use cgmath::Matrix4;
+use super::LoaderError;
+use cgmath::{Matrix4, SquareMatrix};
 use gltf::Skin as gltfSkin;
 
 #[derive(Debug, Clone)]
@@ -7,23 +8,32 @@ pub struct Skin {
 }
 
 impl Skin {
-    pub fn from_gltf(skin: &gltfSkin, buf: &[gltf::buffer::Data]) -> Self {
+    pub fn from_gltf(skin: &gltfSkin, buf: &[gltf::buffer::Data]) -> Result<Self, LoaderError> {
         let reader = skin.reader(|buffer| Some(&buf[buffer.index()]));
-
-        let matrices: Vec<Matrix4<f32>> = reader
-            .read_inverse_bind_matrices()
-            .expect("Skinned mesh should have bind matrices.")
-            .map(Matrix4::from)
-            .collect();
         let joints: Vec<usize> = skin.joints().map(|node| node.index()).collect();
 
-        Self {
+        // without bind matrices every joint uses the identity
+        let matrices: Vec<Matrix4<f32>> = match reader.read_inverse_bind_matrices() {
+            Some(matrices) => matrices.map(Matrix4::from).collect(),
+            None => vec![Matrix4::identity(); joints.len()],
+        };
+
+        if matrices.len() < joints.len() {
+            return Err(LoaderError::BindMatrixCount {
+                skin: skin.index(),
+                accessor: skin.inverse_bind_matrices().map_or(0, |a| a.index()),
+                found: matrices.len(),
+                joints: joints.len(),
+            });
+        }
+
+        Ok(Self {
             joints: matrices
                 .into_iter()
                 .zip(joints.into_iter())
                 .map(|(m, n)| Joint::new(m, n))
                 .collect(),
-        }
+        })
     }
 }
 #[derive(Debug, Copy, Clone)]
@@ -0,0 +1,816 @@
+use crate::ImRender;
+use imgui::Ui;
+
+use super::{Animation, LoaderError, Playback, Pose, Wrap};
+
+use std::{cmp::Ordering, fmt, path::Path};
+
+/// Value of a state machine parameter.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Param {
+    Float(f32),
+    Bool(bool),
+    Trigger(bool), // stays set until a transition uses it
+}
+
+/// What a state plays.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Motion {
+    Clip(usize),
+    // 1D blend space, (position on the parameter, clip) pairs sorted by position
+    Blend {
+        param: String,
+        clips: Vec<(f32, usize)>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct State {
+    pub name: String,
+    pub motion: Motion,
+    pub speed: f32,
+    pub wrap: Wrap,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Greater(String, f32),
+    Less(String, f32),
+    Is(String, bool), // bool parameters, or a set trigger
+}
+
+#[derive(Debug, Clone)]
+pub struct Transition {
+    pub from: Option<usize>, // None goes from any state
+    pub to: usize,
+    pub conditions: Vec<Condition>,
+    pub exit_time: Option<f32>, // fraction of the state that has to play first
+    pub duration: f32,          // length of the cross-fade, in seconds
+}
+
+/// Picks the clips to play from a set of parameters.
+///
+/// The first state is the entry state. Transitions are checked in order once the cross-fade
+/// of the last one is over, the first one whose conditions hold is taken.
+#[derive(Debug, Clone)]
+pub struct StateMachine {
+    pub states: Vec<State>,
+    pub transitions: Vec<Transition>,
+    pub params: Vec<(String, Param)>,
+    current: Active,
+    fade: Option<Fade>,
+}
+
+// a state being played
+#[derive(Debug, Copy, Clone)]
+struct Active {
+    state: usize,
+    playback: Playback,
+    elapsed: f32, // time spent in the state, scaled by its speed
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Fade {
+    from: Active,
+    transition: usize,
+    elapsed: f32,
+}
+
+impl StateMachine {
+    pub fn new(
+        states: Vec<State>,
+        transitions: Vec<Transition>,
+        params: Vec<(String, Param)>,
+    ) -> Self {
+        assert!(!states.is_empty(), "a state machine needs a state");
+
+        let mut machine = Self {
+            states,
+            transitions,
+            params,
+            current: Active::default(),
+            fade: None,
+        };
+        machine.start(0);
+
+        machine
+    }
+
+    /// Reads a state machine from a text file, clips are looked up by name in `clips`.
+    ///
+    /// ```text
+    /// # the first state is the entry state
+    /// param speed float 0
+    /// param jump trigger
+    ///
+    /// state idle clip Idle
+    /// state move blend speed Walk:1 Run:4 speed 1.2
+    /// state jump clip Jump wrap once
+    ///
+    /// transition idle move when speed > 0.1 blend 0.2
+    /// transition move idle when speed < 0.1 blend 0.2
+    /// transition any jump when jump blend 0.1
+    /// transition jump idle exit 0.9 blend 0.3
+    /// ```
+    pub fn load<P: AsRef<Path>>(path: P, clips: &[Animation]) -> Result<Self, LoaderError> {
+        Self::parse(&std::fs::read_to_string(path)?, clips)
+    }
+
+    /// Same as `load`, from the contents of the file.
+    pub fn parse(text: &str, clips: &[Animation]) -> Result<Self, LoaderError> {
+        let mut params = Vec::new();
+        let mut states = Vec::new();
+        let mut transitions = Vec::new();
+
+        // transitions may name states declared after them
+        let mut pending = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let err = |message: String| LoaderError::StateMachine {
+                line: line_no,
+                message,
+            };
+
+            let line = line.split('#').next().unwrap();
+            let words: Vec<&str> = line.split_whitespace().collect();
+
+            match words.as_slice() {
+                [] => {}
+                ["param", name, kind, rest @ ..] => {
+                    params.push((name.to_string(), parse_param(kind, rest).map_err(err)?))
+                }
+                ["state", name, rest @ ..] => {
+                    states.push(parse_state(name, rest, clips, &params).map_err(err)?)
+                }
+                ["transition", from, to, rest @ ..] => {
+                    pending.push((line_no, *from, *to, rest.to_vec()))
+                }
+                _ => return Err(err(format!("can't parse '{}'", line.trim()))),
+            }
+        }
+
+        if states.is_empty() {
+            return Err(LoaderError::StateMachine {
+                line: 0,
+                message: "no states".to_string(),
+            });
+        }
+
+        for (line, from, to, rest) in pending {
+            let err = |message: String| LoaderError::StateMachine { line, message };
+            transitions.push(parse_transition(from, to, &rest, &states, &params).map_err(err)?);
+        }
+
+        Ok(Self::new(states, transitions, params))
+    }
+
+    pub fn param(&self, name: &str) -> Option<Param> {
+        self.params.iter().find(|(n, _)| n == name).map(|&(_, p)| p)
+    }
+
+    /// Sets a parameter, it's added if the machine doesn't have it.
+    pub fn set_param(&mut self, name: &str, value: Param) {
+        match self.params.iter_mut().find(|(n, _)| n == name) {
+            Some((_, param)) => *param = value,
+            None => self.params.push((name.to_string(), value)),
+        }
+    }
+
+    pub fn set_float(&mut self, name: &str, value: f32) {
+        self.set_param(name, Param::Float(value));
+    }
+
+    pub fn set_bool(&mut self, name: &str, value: bool) {
+        self.set_param(name, Param::Bool(value));
+    }
+
+    pub fn set_trigger(&mut self, name: &str) {
+        self.set_param(name, Param::Trigger(true));
+    }
+
+    /// Index of the state being played.
+    #[inline]
+    pub fn current(&self) -> usize {
+        self.current.state
+    }
+
+    /// The transition being cross-faded and how far along it is, between zero and one.
+    pub fn fading(&self) -> Option<(usize, f32)> {
+        self.fade.map(|fade| {
+            let duration = self.transitions[fade.transition].duration;
+            (fade.transition, fade.elapsed / duration)
+        })
+    }
+
+    /// How much of the current state has played, goes past one for looping clips.
+    pub fn progress(&self, clips: &[Animation]) -> f32 {
+        self.current
+            .progress(self.duration(self.current.state, clips))
+    }
+
+    /// Jumps to `state` without cross-fading.
+    pub fn start(&mut self, state: usize) {
+        assert!(state < self.states.len(), "state index out of bounds");
+
+        self.current = self.enter(state);
+        self.fade = None;
+    }
+
+    /// Plays the states for `delta` seconds and takes the transitions that are due.
+    pub fn advance(&mut self, delta: f32, clips: &[Animation]) {
+        let duration = self.duration(self.current.state, clips);
+        self.current.advance(delta, duration);
+
+        if let Some(mut fade) = self.fade {
+            let duration = self.duration(fade.from.state, clips);
+            fade.from.advance(delta, duration);
+            fade.elapsed += delta;
+
+            self.fade = Some(fade).filter(|f| f.elapsed < self.transitions[f.transition].duration);
+        }
+
+        // transitions don't interrupt a cross-fade
+        if self.fade.is_some() {
+            return;
+        }
+
+        let progress = self.current.progress(duration);
+        let taken = self.transitions.iter().position(|t| {
+            let from = t.from.map_or(t.to != self.current.state, |from| {
+                from == self.current.state
+            });
+
+            from && t.exit_time.is_none_or(|exit| progress >= exit)
+                && t.conditions.iter().all(|c| self.holds(c))
+        });
+
+        if let Some(index) = taken {
+            self.take(index);
+        }
+    }
+
+    /// The pose of the current state, blended with the one it's fading from.
+    pub fn pose(&self, clips: &[Animation]) -> Pose {
+        let pose = self.sample(self.current, clips);
+
+        match self.fade {
+            Some(fade) => {
+                let (_, t) = self.fading().unwrap();
+                self.sample(fade.from, clips).blend(&pose, t)
+            }
+            None => pose,
+        }
+    }
+
+    fn take(&mut self, index: usize) {
+        let transition = &self.transitions[index];
+
+        // triggers are used up by the transition that checks them
+        let used: Vec<String> = transition
+            .conditions
+            .iter()
+            .filter_map(|c| match c {
+                Condition::Is(name, true) => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+        for (name, param) in self.params.iter_mut() {
+            if let Param::Trigger(set) = param {
+                *set &= !used.contains(name);
+            }
+        }
+
+        let from = self.current;
+        self.current = self.enter(transition.to);
+        self.fade = Some(Fade {
+            from,
+            transition: index,
+            elapsed: 0.0,
+        })
+        .filter(|_| transition.duration > 0.0);
+    }
+
+    fn enter(&self, state: usize) -> Active {
+        let mut playback = Playback::new(0.0);
+        playback.speed = self.states[state].speed;
+        playback.wrap = self.states[state].wrap;
+        playback.playing = true;
+
+        Active {
+            state,
+            playback,
+            elapsed: 0.0,
+        }
+    }
+
+    fn holds(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Greater(name, value) => self.float(name) > *value,
+            Condition::Less(name, value) => self.float(name) < *value,
+            Condition::Is(name, value) => match self.param(name) {
+                Some(Param::Bool(set)) | Some(Param::Trigger(set)) => set == *value,
+                _ => false,
+            },
+        }
+    }
+
+    fn float(&self, name: &str) -> f32 {
+        match self.param(name) {
+            Some(Param::Float(value)) => value,
+            _ => 0.0,
+        }
+    }
+
+    // clips of a state with their weights
+    fn clips(&self, state: usize) -> Vec<(usize, f32)> {
+        match &self.states[state].motion {
+            Motion::Clip(clip) => vec![(*clip, 1.0)],
+            Motion::Blend { param, clips } => {
+                let value = self.float(param);
+                let above = clips.iter().position(|&(pos, _)| pos > value);
+
+                match above {
+                    Some(0) => vec![(clips[0].1, 1.0)],
+                    Some(i) => {
+                        let (a, b) = (clips[i - 1], clips[i]);
+                        let t = (value - a.0) / (b.0 - a.0);
+                        vec![(a.1, 1.0 - t), (b.1, t)]
+                    }
+                    None => vec![(clips[clips.len() - 1].1, 1.0)],
+                }
+            }
+        }
+    }
+
+    // length of a state, blend spaces stretch their clips to the weighted length
+    fn duration(&self, state: usize, clips: &[Animation]) -> f32 {
+        self.clips(state)
+            .iter()
+            .map(|&(clip, weight)| clips[clip].duration() * weight)
+            .sum()
+    }
+
+    fn sample(&self, active: Active, clips: &[Animation]) -> Pose {
+        let duration = active.playback.duration();
+        let phase = if duration > 0.0 {
+            active.playback.time() / duration
+        } else {
+            0.0
+        };
+
+        let poses: Vec<(Pose, f32)> = self
+            .clips(active.state)
+            .into_iter()
+            .map(|(clip, weight)| (clips[clip].sample(phase * clips[clip].duration()), weight))
+            .collect();
+        let weighted: Vec<(&Pose, f32)> = poses.iter().map(|(pose, w)| (pose, *w)).collect();
+
+        Pose::mix(&weighted)
+    }
+}
+
+impl Default for Active {
+    fn default() -> Self {
+        Self {
+            state: 0,
+            playback: Playback::new(0.0),
+            elapsed: 0.0,
+        }
+    }
+}
+
+impl Active {
+    fn advance(&mut self, delta: f32, duration: f32) {
+        // blend spaces change length with their parameter, the phase is kept
+        let old = self.playback.duration();
+        if duration != old {
+            let phase = self.playback.time() / old;
+            self.playback.set_duration(duration);
+
+            if old > 0.0 {
+                self.playback.seek(phase * duration);
+            } else {
+                // just entered
+                self.playback.rewind();
+            }
+        }
+
+        if self.playback.playing {
+            self.elapsed += (delta * self.playback.speed).abs();
+        }
+        self.playback.advance(delta);
+
+        let end = if self.playback.speed < 0.0 {
+            0.0
+        } else {
+            duration
+        };
+        if self.playback.wrap == Wrap::Clamp && self.playback.time() == end {
+            self.playback.playing = false;
+        }
+    }
+
+    fn progress(&self, duration: f32) -> f32 {
+        if duration > 0.0 {
+            self.elapsed / duration
+        } else {
+            1.0
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Greater(name, value) => write!(f, "{} > {}", name, value),
+            Condition::Less(name, value) => write!(f, "{} < {}", name, value),
+            Condition::Is(name, true) => write!(f, "{}", name),
+            Condition::Is(name, false) => write!(f, "!{}", name),
+        }
+    }
+}
+
+fn parse_param(kind: &str, rest: &[&str]) -> Result<Param, String> {
+    let value = rest.first();
+
+    match kind {
+        "float" => value
+            .map_or(Ok(0.0), |v| v.parse())
+            .map(Param::Float)
+            .map_err(|_| format!("bad float '{}'", value.unwrap())),
+        "bool" => value
+            .map_or(Ok(false), |v| v.parse())
+            .map(Param::Bool)
+            .map_err(|_| format!("bad bool '{}'", value.unwrap())),
+        "trigger" => Ok(Param::Trigger(false)),
+        _ => Err(format!("unknown parameter type '{}'", kind)),
+    }
+}
+
+fn parse_state(
+    name: &str,
+    words: &[&str],
+    clips: &[Animation],
+    params: &[(String, Param)],
+) -> Result<State, String> {
+    let clip = |name: &str| {
+        clips
+            .iter()
+            .position(|c| c.name == name)
+            .ok_or_else(|| format!("no clip named '{}'", name))
+    };
+
+    let (motion, mut rest) = match words {
+        ["clip", name, rest @ ..] => (Motion::Clip(clip(name)?), rest),
+        ["blend", param, rest @ ..] => {
+            float_param(param, params)?;
+
+            let count = rest.iter().take_while(|w| w.contains(':')).count();
+            let mut points = Vec::new();
+            for point in rest[..count].iter() {
+                let (name, pos) = point.rsplit_once(':').unwrap();
+                let pos: f32 = pos.parse().map_err(|_| format!("bad position '{}'", pos))?;
+                points.push((pos, clip(name)?));
+            }
+
+            if points.is_empty() {
+                return Err("a blend needs clips, as clip:position".to_string());
+            }
+            points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+            let motion = Motion::Blend {
+                param: param.to_string(),
+                clips: points,
+            };
+            (motion, &rest[count..])
+        }
+        _ => return Err(format!("state '{}' needs a clip or a blend", name)),
+    };
+
+    let mut state = State {
+        name: name.to_string(),
+        motion,
+        speed: 1.0,
+        wrap: Wrap::Loop,
+    };
+
+    while !rest.is_empty() {
+        match rest {
+            ["speed", value, tail @ ..] => {
+                state.speed = value
+                    .parse()
+                    .map_err(|_| format!("bad speed '{}'", value))?;
+                rest = tail;
+            }
+            ["wrap", value, tail @ ..] => {
+                state.wrap = match *value {
+                    "loop" => Wrap::Loop,
+                    // a one-shot state holds its last pose until a transition leaves it
+                    "once" => Wrap::Clamp,
+                    "clamp" => Wrap::Clamp,
+                    "pingpong" => Wrap::PingPong,
+                    _ => return Err(format!("unknown wrap '{}'", value)),
+                };
+                rest = tail;
+            }
+            _ => return Err(format!("unexpected '{}'", rest.join(" "))),
+        }
+    }
+
+    Ok(state)
+}
+
+fn parse_transition(
+    from: &str,
+    to: &str,
+    words: &[&str],
+    states: &[State],
+    params: &[(String, Param)],
+) -> Result<Transition, String> {
+    let state = |name: &str| {
+        states
+            .iter()
+            .position(|s| s.name == name)
+            .ok_or_else(|| format!("no state named '{}'", name))
+    };
+
+    let mut transition = Transition {
+        from: if from == "any" {
+            None
+        } else {
+            Some(state(from)?)
+        },
+        to: state(to)?,
+        conditions: Vec::new(),
+        exit_time: None,
+        duration: 0.0,
+    };
+
+    let mut rest = words;
+    while !rest.is_empty() {
+        match rest {
+            ["when", tail @ ..] | ["and", tail @ ..] => {
+                let (condition, tail) = parse_condition(tail, params)?;
+                transition.conditions.push(condition);
+                rest = tail;
+            }
+            ["exit", value, tail @ ..] => {
+                let exit = value
+                    .parse()
+                    .map_err(|_| format!("bad exit time '{}'", value))?;
+                transition.exit_time = Some(exit);
+                rest = tail;
+            }
+            ["blend", value, tail @ ..] => {
+                transition.duration = value
+                    .parse::<f32>()
+                    .map_err(|_| format!("bad blend duration '{}'", value))?
+                    .max(0.0);
+                rest = tail;
+            }
+            _ => return Err(format!("unexpected '{}'", rest.join(" "))),
+        }
+    }
+
+    if transition.conditions.is_empty() && transition.exit_time.is_none() {
+        return Err("a transition needs a condition or an exit time".to_string());
+    }
+
+    Ok(transition)
+}
+
+fn parse_condition<'a, 'b>(
+    words: &'a [&'b str],
+    params: &[(String, Param)],
+) -> Result<(Condition, &'a [&'b str]), String> {
+    match words {
+        [name, op @ ("<" | ">"), value, tail @ ..] => {
+            float_param(name, params)?;
+            let value = value
+                .parse()
+                .map_err(|_| format!("bad value '{}'", value))?;
+
+            let condition = if *op == ">" {
+                Condition::Greater(name.to_string(), value)
+            } else {
+                Condition::Less(name.to_string(), value)
+            };
+            Ok((condition, tail))
+        }
+        [name, tail @ ..] => {
+            let (name, value) = match name.strip_prefix('!') {
+                Some(name) => (name, false),
+                None => (*name, true),
+            };
+
+            match params.iter().find(|(n, _)| n == name) {
+                Some((_, Param::Bool(_))) => Ok((Condition::Is(name.to_string(), value), tail)),
+                Some((_, Param::Trigger(_))) if value => {
+                    Ok((Condition::Is(name.to_string(), value), tail))
+                }
+                Some(_) => Err(format!("'{}' can't be used as a flag", name)),
+                None => Err(format!("no parameter named '{}'", name)),
+            }
+        }
+        [] => Err("missing condition".to_string()),
+    }
+}
+
+fn float_param(name: &str, params: &[(String, Param)]) -> Result<(), String> {
+    match params.iter().find(|(n, _)| n == name) {
+        Some((_, Param::Float(_))) => Ok(()),
+        Some(_) => Err(format!("'{}' isn't a float parameter", name)),
+        None => Err(format!("no parameter named '{}'", name)),
+    }
+}
+
+impl ImRender for StateMachine {
+    fn render(&mut self, ui: &Ui) {
+        let current = &self.states[self.current.state].name;
+        match (self.fade, self.fading()) {
+            (Some(fade), Some((_, t))) => ui.text(format!(
+                "{} -> {} ({:.0}%)",
+                self.states[fade.from.state].name,
+                current,
+                t * 100.0
+            )),
+            _ => ui.text(format!("State: {}", current)),
+        }
+
+        ui.separator();
+        for (name, param) in self.params.iter_mut() {
+            let label = imgui::im_str!("{}", name);
+            match param {
+                Param::Float(value) => {
+                    ui.input_float(&label, value).build();
+                }
+                Param::Bool(value) => {
+                    ui.checkbox(&label, value);
+                }
+                Param::Trigger(set) => {
+                    *set |= ui.button(&label, [0.0, 0.0]);
+                    if *set {
+                        ui.same_line(0.0);
+                        ui.text("set");
+                    }
+                }
+            }
+        }
+
+        ui.separator();
+        let mut selected = None;
+        for (i, state) in self.states.iter().enumerate() {
+            if imgui::Selectable::new(&imgui::im_str!("{}", state.name))
+                .selected(i == self.current.state)
+                .build(ui)
+            {
+                selected = Some(i);
+            }
+        }
+        if let Some(state) = selected {
+            self.start(state);
+        }
+
+        ui.separator();
+        for transition in self.transitions.iter() {
+            let from = transition.from.map_or("any", |s| &self.states[s].name);
+            let mut text = format!("{} -> {}", from, self.states[transition.to].name);
+
+            let conditions: Vec<String> = transition
+                .conditions
+                .iter()
+                .map(|c| c.to_string())
+                .collect();
+            if !conditions.is_empty() {
+                text += &format!(" when {}", conditions.join(" and "));
+            }
+            if let Some(exit) = transition.exit_time {
+                text += &format!(" exit {}", exit);
+            }
+            if transition.duration > 0.0 {
+                text += &format!(" blend {}", transition.duration);
+            }
+
+            ui.text(text);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{test_util::test_asset, Animations, SceneData};
+
+    const MACHINE: &str = "
+        param speed float
+        param jump trigger
+
+        state idle clip Raise   # the entry state
+        state move blend speed Raise:0 Raise:2 speed 2
+        state jump clip Raise wrap once
+
+        transition idle move when speed > 0.5 blend 0.5
+        transition move idle when speed < 0.5
+        transition any jump when jump blend 0.25
+        transition jump idle exit 0.75
+    ";
+
+    fn clips() -> Vec<Animation> {
+        let path = test_asset("morph.gltf");
+        SceneData::load(path).unwrap().animations
+    }
+
+    #[test]
+    fn parse() {
+        let clips = clips();
+        let machine = StateMachine::parse(MACHINE, &clips).unwrap();
+
+        assert_eq!(machine.states.len(), 3);
+        assert_eq!(machine.states[1].speed, 2.0);
+        assert_eq!(machine.states[2].wrap, Wrap::Clamp);
+        assert_eq!(
+            machine.transitions[0].conditions,
+            vec![Condition::Greater("speed".to_string(), 0.5)]
+        );
+        assert_eq!(machine.transitions[2].from, None);
+        assert_eq!(machine.transitions[3].exit_time, Some(0.75));
+
+        let line = |text: &str| match StateMachine::parse(text, &clips) {
+            Err(LoaderError::StateMachine { line, .. }) => line,
+            other => panic!("expected an error, got {:?}", other),
+        };
+        assert_eq!(line("state idle clip Missing"), 1);
+        assert_eq!(
+            line("state idle clip Raise\ntransition idle idle when nope"),
+            2
+        );
+        assert_eq!(
+            line("param jump trigger\nstate a clip Raise\ntransition any a when !jump"),
+            3
+        );
+        assert_eq!(line("state a clip Raise\ntransition a a"), 2);
+    }
+
+    #[test]
+    fn transitions() {
+        let clips = clips();
+        let mut machine = StateMachine::parse(MACHINE, &clips).unwrap();
+
+        machine.advance(0.5, &clips);
+        assert_eq!(machine.current(), 0);
+        assert_eq!(machine.pose(&clips).weights[0].1, vec![0.5]);
+
+        // cross-fades into the blend space
+        machine.set_float("speed", 1.0);
+        machine.advance(0.0, &clips);
+        assert_eq!(machine.current(), 1);
+        machine.advance(0.25, &clips);
+        assert_eq!(machine.fading(), Some((0, 0.5)));
+
+        // the fade isn't interrupted, the trigger is used up once it's over
+        machine.set_trigger("jump");
+        machine.advance(0.0, &clips);
+        assert_eq!(machine.current(), 1);
+        machine.advance(0.25, &clips);
+        assert_eq!(machine.current(), 2);
+        assert_eq!(machine.fading(), Some((2, 0.0)));
+        assert_eq!(machine.param("jump"), Some(Param::Trigger(false)));
+
+        // back to idle once three quarters of the jump played
+        machine.start(2);
+        machine.advance(0.5, &clips);
+        assert_eq!(machine.current(), 2);
+        machine.advance(0.25, &clips);
+        assert_eq!(machine.current(), 0);
+
+        // a one-shot state without a way out stays on its last frame
+        let text = "state jump clip Raise wrap once";
+        let mut machine = StateMachine::parse(text, &clips).unwrap();
+        machine.advance(0.75, &clips);
+        machine.advance(0.75, &clips);
+        assert_eq!(machine.pose(&clips).weights[0].1, vec![1.0]);
+        assert!(!machine.current.playback.playing);
+    }
+
+    #[test]
+    fn pause() {
+        let path = test_asset("morph.gltf");
+        let mut data = SceneData::load(path).unwrap();
+        let mut animations = Animations::new(data.animations.clone());
+        animations.machine = Some(StateMachine::parse(MACHINE, &animations.inner).unwrap());
+
+        // paused, the machine doesn't move
+        assert!(!animations.animate(0.5, &mut data.nodes));
+        let machine = animations.machine.as_ref().unwrap();
+        assert_eq!(machine.progress(&animations.inner), 0.0);
+
+        animations.playback.playing = true;
+        assert!(animations.animate(0.5, &mut data.nodes));
+        assert_eq!(data.nodes[0].weights, vec![0.5]);
+
+        animations.playback.playing = false;
+        assert!(!animations.animate(0.25, &mut data.nodes));
+        assert_eq!(data.nodes[0].weights, vec![0.5]);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::aabb::Aabb;
+use cgmath::Matrix4;
+
+use super::{loader::LoaderError, Scene, SceneData};
+
+use std::path::{Path, PathBuf};
+
+/// Several scenes laid out in one space.
+///
+/// Each file is loaded once and every placement of it draws an instance of that `Scene`: the
+/// meshes, textures and materials are shared, the nodes and animations belong to the placement.
+#[derive(Debug, Default)]
+pub struct World {
+    pub scenes: Vec<Scene>, // as loaded, the placements draw their own instances
+    pub placements: Vec<Placement>,
+    paths: Vec<Option<PathBuf>>, // file of each scene, None for the ones added directly
+}
+
+/// One copy of a scene in the world.
+#[derive(Debug)]
+pub struct Placement {
+    pub source: usize, // index in `World::scenes`
+    pub scene: Scene,  // see `Scene::instance`
+    pub transform: Matrix4<f32>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads every model and places it at its transform.
+    ///
+    /// The files are read in parallel, files given more than once are only loaded once.
+    pub fn load<P: AsRef<Path>>(models: &[(P, Matrix4<f32>)]) -> Result<Self, LoaderError> {
+        let mut world = Self::new();
+
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for (path, _) in models.iter() {
+            let path = file_key(path.as_ref());
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+
+        // only the CPU side can be loaded off the main thread, the upload needs the GL context
+        let loaded = SceneData::load_many(&paths);
+        for (path, data) in paths.into_iter().zip(loaded) {
+            world.scenes.push(Scene::from(data?));
+            world.paths.push(Some(path));
+        }
+
+        for (path, transform) in models.iter() {
+            let scene = world.scene_of(path.as_ref()).unwrap();
+            world.place(scene, *transform);
+        }
+
+        Ok(world)
+    }
+
+    /// Places a model at `transform`, the file is only loaded if it isn't in the world yet.
+    ///
+    /// Returns the index of the placement.
+    pub fn add<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        transform: Matrix4<f32>,
+    ) -> Result<usize, LoaderError> {
+        let scene = match self.scene_of(path.as_ref()) {
+            Some(scene) => scene,
+            None => {
+                self.scenes.push(Scene::load(path.as_ref())?);
+                self.paths.push(Some(file_key(path.as_ref())));
+                self.scenes.len() - 1
+            }
+        };
+
+        Ok(self.place(scene, transform))
+    }
+
+    /// Adds an already built scene, it isn't placed anywhere yet.
+    pub fn add_scene(&mut self, scene: Scene) -> usize {
+        self.scenes.push(scene);
+        self.paths.push(None);
+        self.scenes.len() - 1
+    }
+
+    /// Places another copy of `scene`, returns the index of the placement.
+    ///
+    /// The copy is an instance of the scene as it is now, it animates and can be edited on its
+    /// own.
+    pub fn place(&mut self, scene: usize, transform: Matrix4<f32>) -> usize {
+        assert!(scene < self.scenes.len(), "scene index out of bounds");
+
+        self.placements.push(Placement {
+            source: scene,
+            scene: self.scenes[scene].instance(),
+            transform,
+        });
+        self.placements.len() - 1
+    }
+
+    /// Index of the scene loaded from `path`.
+    pub fn scene_of(&self, path: &Path) -> Option<usize> {
+        let key = Some(file_key(path));
+        self.paths.iter().position(|p| *p == key)
+    }
+
+    pub fn update(&mut self, time: f32) {
+        for placement in self.placements.iter_mut() {
+            placement.scene.update(time);
+        }
+    }
+
+    /// Bounding box of every placement.
+    pub fn aabb(&self) -> Aabb {
+        self.placements.iter().fold(Aabb::default(), |aabb, p| {
+            let (corners, _) = p.scene.aabb.gen_vertices();
+            let placed = corners
+                .into_iter()
+                .map(|c| (p.transform * c.extend(1.0)).truncate());
+
+            aabb.surrounds(&Aabb::from_points(placed))
+        })
+    }
+}
+
+// the same file may be reached through different paths
+fn file_key(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}